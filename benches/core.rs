@@ -0,0 +1,67 @@
+use std::{fs, hint::black_box, path::PathBuf};
+
+use criterion::{Criterion, criterion_group, criterion_main};
+use muman::internals::{
+    DirtyTrack, base_title, main_artist, normalize_lyrics, quick_hash, recurse_directory,
+};
+
+/// Scratch folder for the benchmark files, recreated on each run.
+fn scratch(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("muman-bench-{}", name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn hashing(c: &mut Criterion) {
+    let dir = scratch("hashing");
+    let file = dir.join("track.flac");
+    fs::write(&file, vec![0x5a; 8 * 1024 * 1024]).unwrap();
+    c.bench_function("quick_hash 8 MiB", |b| {
+        b.iter(|| quick_hash(black_box(&file)))
+    });
+
+    let track = DirtyTrack {
+        title: Some("Paranoid Android".to_string()),
+        artist: Some("Radiohead".to_string()),
+        album: Some("OK Computer".to_string()),
+        track_number: Some(2),
+        year: Some(1997),
+        ..Default::default()
+    };
+    c.bench_function("tag_hash", |b| b.iter(|| black_box(&track).tag_hash()));
+}
+
+fn scanning(c: &mut Criterion) {
+    let dir = scratch("scanning");
+    for album in 0..50 {
+        let album_dir = dir
+            .join(format!("Artist {}", album % 10))
+            .join(format!("Album {}", album));
+        fs::create_dir_all(&album_dir).unwrap();
+        for track in 0..20 {
+            fs::write(album_dir.join(format!("{:02} Track.flac", track)), b"").unwrap();
+        }
+        fs::write(album_dir.join("cover.jpg"), b"").unwrap();
+    }
+    let filter = |path: &PathBuf| path.extension().is_some_and(|e| e == "flac");
+    c.bench_function("recurse_directory 1000 files", |b| {
+        b.iter(|| recurse_directory(black_box(&dir), true, Some(&filter), None))
+    });
+}
+
+fn normalization(c: &mut Criterion) {
+    c.bench_function("base_title", |b| {
+        b.iter(|| base_title(black_box("OK Computer (OKNOTOK 1997 2017) [Disc 2]")))
+    });
+    c.bench_function("main_artist", |b| {
+        b.iter(|| main_artist(black_box("Daft Punk feat. Pharrell Williams")))
+    });
+    let lyrics = "[00:01.00]Please could you stop the noise  \r\n".repeat(60);
+    c.bench_function("normalize lyrics", |b| {
+        b.iter(|| normalize_lyrics(black_box(&lyrics)))
+    });
+}
+
+criterion_group!(benches, hashing, scanning, normalization);
+criterion_main!(benches);
@@ -1,5 +1,61 @@
-use crate::{artist::Artist, track::Track};
+use std::path::{Path, PathBuf};
 
+use serde::Serialize;
+
+use crate::{
+    artist::{Artist, main_artist},
+    tag::embedded_cover,
+    track::{DirtyTrack, Track},
+};
+
+/// Usual names of cover images stored next to the tracks, by preference.
+const COVER_NAMES: &[&str] = &["cover", "folder", "front", "album"];
+const COVER_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+
+/// Words of an album title revealing its edition, and the label shown for them.
+const EDITION_WORDS: &[(&str, &str)] = &[
+    ("deluxe", "Deluxe"),
+    ("remaster", "Remaster"),
+    ("remastered", "Remaster"),
+    ("live", "Live"),
+];
+
+/// Tag fields holding the release type, as written by MusicBrainz Picard and other taggers.
+const RELEASE_TYPE_FIELDS: &[&str] = &["releasetype", "musicbrainz_albumtype"];
+/// Releases shorter than this are singles or EPs, depending on their track count.
+const SHORT_RELEASE_SECS: u32 = 30 * 60;
+const SINGLE_MAX_TRACKS: usize = 3;
+const EP_MAX_TRACKS: usize = 6;
+
+/// Kind of release an album is.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum ReleaseType {
+    Single,
+    Ep,
+    Album,
+}
+
+impl ReleaseType {
+    pub fn label(self) -> &'static str {
+        match self {
+            ReleaseType::Single => "Single",
+            ReleaseType::Ep => "EP",
+            ReleaseType::Album => "Album",
+        }
+    }
+
+    fn parse(word: &str) -> Option<Self> {
+        match word.trim().to_lowercase().as_str() {
+            "single" => Some(ReleaseType::Single),
+            "ep" => Some(ReleaseType::Ep),
+            "album" | "lp" => Some(ReleaseType::Album),
+            _ => None,
+        }
+    }
+}
+
+#[allow(dead_code)]
 pub struct Album {
     title: String,
     artist: Box<Artist>,
@@ -7,3 +63,131 @@ pub struct Album {
 
     year: u32,
 }
+
+/// Album title without trailing bracketed qualifiers, so "Album [Disc 1]" and
+/// "Album (Japan Edition)" both give "Album".
+pub fn base_title(title: &str) -> &str {
+    let mut title = title.trim();
+    while let Some(stripped) = title
+        .strip_suffix(')')
+        .and_then(|t| t.rfind('(').map(|i| &t[..i]))
+        .or_else(|| {
+            title
+                .strip_suffix(']')
+                .and_then(|t| t.rfind('[').map(|i| &t[..i]))
+        })
+    {
+        let stripped = stripped.trim_end();
+        if stripped.is_empty() {
+            break;
+        }
+        title = stripped;
+    }
+    title
+}
+
+/// Cover image of the album in `folder`, such as "cover.jpg" or "Folder.png".
+pub fn folder_cover(folder: &Path) -> Option<PathBuf> {
+    let images: Vec<(String, PathBuf)> = std::fs::read_dir(folder)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let extension = path.extension()?.to_str()?.to_lowercase();
+            COVER_EXTENSIONS
+                .contains(&extension.as_str())
+                .then(|| Some((path.file_stem()?.to_str()?.to_lowercase(), path.clone())))?
+        })
+        .collect();
+    COVER_NAMES.iter().find_map(|name| {
+        images
+            .iter()
+            .find(|(stem, _)| stem == name)
+            .map(|(_, path)| path.clone())
+    })
+}
+
+/// Cover of the album in `folder`, from a folder image or else embedded in one of its tracks, with
+/// the file extension matching its format.
+pub fn album_cover(folder: &Path, tracks: &[&DirtyTrack]) -> Option<(Vec<u8>, String)> {
+    match folder_cover(folder) {
+        Some(path) => {
+            let extension = path.extension()?.to_str()?.to_lowercase();
+            Some((std::fs::read(&path).ok()?, extension))
+        }
+        None => {
+            let (data, extension) = tracks
+                .iter()
+                .filter(|t| t.embedded_cover)
+                .find_map(|t| embedded_cover(t.file_path.as_deref()?))?;
+            Some((data, extension.to_string()))
+        }
+    }
+}
+
+/// Edition labels found in an album title, e.g. "Deluxe" and "Remaster" for
+/// "Album (2011 Remastered Deluxe Edition)".
+pub fn editions(title: &str) -> Vec<&'static str> {
+    let mut labels = Vec::new();
+    for word in title
+        .split(|c: char| !c.is_alphanumeric())
+        .map(str::to_lowercase)
+    {
+        if let Some((_, label)) = EDITION_WORDS.iter().find(|(w, _)| *w == word)
+            && !labels.contains(label)
+        {
+            labels.push(*label);
+        }
+    }
+    labels
+}
+
+/// Artist an album is listed under: its album artist, or else the main artist of its tracks.
+pub fn album_artist<'a>(tracks: &[&'a DirtyTrack]) -> &'a str {
+    tracks
+        .iter()
+        .find_map(|t| t.album_artist.as_deref())
+        .or_else(|| tracks.iter().find_map(|t| t.artist.as_deref()))
+        .map_or("Unknown artist", main_artist)
+}
+
+/// Country of an album: the release country of its tracks, else the ISRC country when every
+/// track with an ISRC shares it.
+pub fn album_country(tracks: &[&DirtyTrack]) -> Option<String> {
+    if let Some(country) = tracks.iter().find_map(|t| t.release_country()) {
+        return Some(country);
+    }
+    let mut countries = tracks.iter().filter_map(|t| t.isrc_country());
+    let first = countries.next()?;
+    countries.all(|country| country == first).then_some(first)
+}
+
+/// Classify a release. A release type tag wins, then a title ending in "EP" or "Single", then the
+/// shape of the release: up to 3 tracks under 30 minutes is a single, up to 6 an EP.
+pub fn release_type(title: &str, tracks: &[&DirtyTrack]) -> ReleaseType {
+    let tagged = tracks.iter().find_map(|t| {
+        RELEASE_TYPE_FIELDS
+            .iter()
+            .filter_map(|field| t.extra_field(field))
+            .flat_map(|value| value.split([';', '/', ',']))
+            .find_map(ReleaseType::parse)
+    });
+    if let Some(release_type) = tagged {
+        return release_type;
+    }
+    if let Some(release_type) = base_title(title)
+        .split(|c: char| !c.is_alphanumeric())
+        .rfind(|word| !word.is_empty())
+        .and_then(ReleaseType::parse)
+        .filter(|t| *t != ReleaseType::Album)
+    {
+        return release_type;
+    }
+
+    let duration: u32 = tracks.iter().filter_map(|t| t.duration).sum();
+    match tracks.len() {
+        _ if duration >= SHORT_RELEASE_SECS => ReleaseType::Album,
+        n if n <= SINGLE_MAX_TRACKS => ReleaseType::Single,
+        n if n <= EP_MAX_TRACKS => ReleaseType::Ep,
+        _ => ReleaseType::Album,
+    }
+}
@@ -1,8 +1,26 @@
 use crate::album::Album;
 
+#[allow(dead_code)]
 pub struct Artist {
     pub name: String,
     albums: Vec<Album>,
 
     genre: String,
 }
+
+/// Main artist of a credit, without featured artists: "A feat. B" gives "A".
+pub fn main_artist(artist: &str) -> &str {
+    let lower = artist.to_ascii_lowercase();
+    [
+        " feat. ",
+        " feat ",
+        " ft. ",
+        " featuring ",
+        " (feat. ",
+        " (ft. ",
+    ]
+    .iter()
+    .filter_map(|separator| lower.find(separator))
+    .min()
+    .map_or(artist, |i| artist[..i].trim_end())
+}
@@ -2,13 +2,842 @@
 
 use std::path::PathBuf;
 
+use crate::{filter::Filter, image::SMALL_COVER};
+
+const AFTER_HELP: &str = "Environment:
+  MUMAN_CONFIG   config file to read instead of $XDG_CONFIG_HOME/muman/config.toml
+  MUMAN_LIBRARY  library used when a command is given none, before the profile's unless
+                 --profile is given
+  MUMAN_JOBS     number of threads, like --jobs
+  MUMAN_OFFLINE  1 to work offline, like --offline
+  NO_COLOR       never color the output, like --no-color
+
+Exit codes:
+  0   success
+  1   failure, nothing was done
+  2   partial failure, some files could not be processed
+  3   nothing matched
+  64  invalid arguments";
+
 #[derive(clap::Parser)]
+#[clap(after_help = AFTER_HELP)]
 pub struct Cli {
     /// Set the level of verbosity
     /// -v for info, -vv for debug, -vvv for trace
-    #[clap(short, long, action = clap::ArgAction::Count)]
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
     pub verbose: u8,
 
-    /// Music library path
-    pub library_path: PathBuf,
+    /// Only print errors
+    #[clap(short, long, global = true)]
+    pub quiet: bool,
+
+    /// Never color the output, also set by NO_COLOR. Colors are otherwise used on terminals
+    #[clap(long, global = true)]
+    pub no_color: bool,
+
+    /// Library profile from the config file to use
+    #[clap(short, long, global = true)]
+    pub profile: Option<String>,
+
+    /// Also write the run summary as JSON to this file
+    #[clap(long, global = true)]
+    pub summary_json: Option<PathBuf>,
+
+    /// Threads for parallel work such as reading tags, defaults to the number of CPUs
+    #[clap(short, long, global = true)]
+    pub jobs: Option<usize>,
+
+    /// Files read at the same time, whatever the number of threads, 8 by default. Keep it low for
+    /// libraries on network shares
+    #[clap(long, global = true)]
+    pub io_jobs: Option<usize>,
+
+    /// Allow commands that modify files to run on "/", the home folder or a folder with few
+    /// audio files
+    #[clap(long, global = true)]
+    pub force_root: bool,
+
+    /// Do not use the network: MusicBrainz lookups fail and webhooks are not called
+    #[clap(long, global = true)]
+    pub offline: bool,
+
+    /// File extensions to scan, separated by commas, e.g. "flac,mp3". Defaults to the profile's,
+    /// else flac, mp3, ogg, opus, m4a, wav and aiff
+    #[clap(long, global = true, value_delimiter = ',')]
+    pub extensions: Option<Vec<String>>,
+
+    /// Move the files that commands delete to the trash instead, where file managers can restore
+    /// them
+    #[clap(long, global = true)]
+    pub trash: bool,
+
+    /// Move the files that commands delete under this folder instead, in a folder named after
+    /// the day and at their full path
+    #[clap(long, global = true, value_name = "DIR", conflicts_with = "trash")]
+    pub quarantine: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(clap::Subcommand)]
+pub enum Command {
+    /// Scan the library and list the tracks found
+    Scan {
+        /// Music library path, defaults to the profile's library. Use "-" to read a newline or
+        /// NUL separated list of files from stdin instead
+        library_path: Option<PathBuf>,
+        /// Move the lyrics and CUE sheets of tracks found moved along with them, and update the
+        /// playlists of the library that list them. Without it, scans only list these changes
+        #[clap(long)]
+        follow_moves: bool,
+    },
+
+    /// Show the metadata of audio files
+    Info {
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Inspect and maintain the scan cache and the library index of tags
+    Cache {
+        #[clap(subcommand)]
+        action: CacheAction,
+    },
+
+    /// Generate playlists from the library
+    Playlist {
+        #[clap(subcommand)]
+        action: PlaylistAction,
+    },
+
+    /// Tag maintenance
+    Tag {
+        #[clap(subcommand)]
+        action: TagAction,
+    },
+
+    /// Album level checks and fixes
+    Albums {
+        #[clap(subcommand)]
+        action: AlbumsAction,
+    },
+
+    /// Artist folder checks and fixes
+    Artists {
+        #[clap(subcommand)]
+        action: ArtistsAction,
+    },
+
+    /// Lyrics maintenance
+    Lyrics {
+        #[clap(subcommand)]
+        action: LyricsAction,
+    },
+
+    /// What muman did to your files: tag edits, renames, deletions and writes, from the journal
+    History {
+        /// Only show changes to this file or folder
+        path: Option<PathBuf>,
+        /// Only show changes of the last days
+        #[clap(long)]
+        days: Option<u64>,
+        /// Only show changes made by this command, e.g. "tag"
+        #[clap(long)]
+        command: Option<String>,
+        /// Print the raw journal entries as JSON lines
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Put back the files a run of a command deleted or moved: deleted files from the trash or the
+    /// quarantine folder, or from their content in the journal for text files, and moved files to
+    /// where they were. Tag edits are not undone
+    Undo {
+        /// Journal to read, e.g. a copy of it, defaults to the one muman writes
+        journal: Option<PathBuf>,
+        /// Run to undo, as shown by `muman undo` and `muman history --json`. Defaults to the last
+        /// run that deleted or moved files
+        #[clap(long)]
+        run: Option<String>,
+        /// Offer to undo the changes
+        #[clap(long)]
+        apply: bool,
+        /// Undo without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Time each stage of a scan on your library to see where time goes. Neither the cache nor the
+    /// library index is used or updated
+    Bench {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+    },
+
+    /// Library reports
+    Report {
+        #[clap(subcommand)]
+        action: ReportAction,
+    },
+
+    /// Checksum manifests of the library, to check a backup or a restore
+    Backup {
+        #[clap(subcommand)]
+        action: BackupAction,
+    },
+
+    /// Write a first config file by answering a few questions
+    Init,
+
+    /// Inspect the configuration
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+}
+
+impl Command {
+    /// Library path of commands that modify the library, `None` for read-only runs.
+    pub fn modified_library(&self) -> Option<Option<&PathBuf>> {
+        match self {
+            Command::Tag {
+                action:
+                    TagAction::Renumber {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    }
+                    | TagAction::Strip {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    }
+                    | TagAction::Isrc {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    },
+            }
+            | Command::Albums {
+                action:
+                    AlbumsAction::Compilations {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    }
+                    | AlbumsAction::Reconcile {
+                        library_path,
+                        apply,
+                        yes,
+                    }
+                    | AlbumsAction::Consistency {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    }
+                    | AlbumsAction::Mirror {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    }
+                    | AlbumsAction::Art {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    },
+            }
+            | Command::Artists {
+                action:
+                    ArtistsAction::Consolidate {
+                        library_path,
+                        apply,
+                        yes,
+                    },
+            }
+            | Command::Lyrics {
+                action:
+                    LyricsAction::Reconcile {
+                        library_path,
+                        apply,
+                        yes,
+                        ..
+                    },
+            } => (*apply || *yes).then_some(library_path.as_ref()),
+            Command::Playlist {
+                action: PlaylistAction::Cue { library_path, .. },
+            } => Some(library_path.as_ref()),
+            Command::Scan {
+                library_path,
+                follow_moves,
+            } => follow_moves.then_some(library_path.as_ref()),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Scan { .. } => "scan",
+            Command::Info { .. } => "info",
+            Command::Cache { .. } => "cache",
+            Command::Albums { .. } => "albums",
+            Command::Artists { .. } => "artists",
+            Command::Tag { .. } => "tag",
+            Command::Playlist { .. } => "playlist",
+            Command::Report { .. } => "report",
+            Command::Lyrics { .. } => "lyrics",
+            Command::History { .. } => "history",
+            Command::Undo { .. } => "undo",
+            Command::Bench { .. } => "bench",
+            Command::Backup { .. } => "backup",
+            Command::Init => "init",
+            Command::Config { .. } => "config",
+        }
+    }
+}
+
+#[derive(clap::Subcommand)]
+pub enum ConfigAction {
+    /// Print the config file. Options are taken from the command line first, then from the
+    /// MUMAN_CONFIG, MUMAN_LIBRARY, MUMAN_JOBS and MUMAN_OFFLINE environment variables, then from
+    /// the command's [commands.<name>] section, then from the top of the config file
+    Show {
+        /// Print the resolved settings and where each one comes from instead
+        #[clap(long)]
+        effective: bool,
+        /// With --effective, resolve the options as this command sees them, e.g. "backup"
+        #[clap(long = "for", value_name = "COMMAND")]
+        command: Option<String>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum CacheAction {
+    /// Show what is cached
+    Status,
+    /// Delete the cache and the library index entirely
+    Clear,
+    /// Drop entries of removed or changed files and rewrite the cache and the library index
+    Vacuum,
+}
+
+#[derive(clap::Subcommand)]
+pub enum AlbumsAction {
+    /// Find albums whose tracks have many different artists and mark them as compilations with
+    /// a common album artist
+    Compilations {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Number of distinct track artists from which an album is a compilation
+        #[clap(long, default_value_t = 3)]
+        min_artists: usize,
+        /// Album artist to set on compilations
+        #[clap(long, default_value = "Various Artists")]
+        album_artist: String,
+        /// Offer to update each compilation
+        #[clap(long)]
+        apply: bool,
+        /// Update without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Check that all tracks of each album embed the same cover, and optionally strip the
+    /// embedded copies in favor of a folder image
+    Art {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Remove the front cover embedded in every track of albums where the art is identical,
+        /// writing it to folder.jpg first if the album has no folder image. Other pictures, such
+        /// as back covers, stay
+        #[clap(long)]
+        strip: bool,
+        /// Flag albums whose cover is narrower or shorter than this many pixels
+        #[clap(long, value_name = "PIXELS", default_value_t = SMALL_COVER)]
+        min_size: u32,
+        /// Offer to strip the art of each album, implies --strip
+        #[clap(long)]
+        apply: bool,
+        /// Strip without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// List the albums of each artist with their editions (Deluxe, Remaster, Live, EP,
+    /// Single), track counts and sizes
+    List {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Print the list as JSON
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// List albums with neither embedded art nor a folder image
+    MissingArt {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Only print the album folders, one per line, to feed an art fetcher
+        #[clap(long)]
+        folders: bool,
+    },
+
+    /// Find albums split over several album tags in one folder, e.g. "Album" and
+    /// "Album [Disc 1]", and offer to unify them
+    Reconcile {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Offer to rewrite the album tag of each split album
+        #[clap(long)]
+        apply: bool,
+        /// Rewrite without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Find albums whose tracks disagree on the album title casing, album artist, year or genre,
+    /// and offer to harmonize them
+    Consistency {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Value the tracks of an album are harmonized to
+        #[clap(long, value_enum, default_value_t = Harmonize::Majority)]
+        prefer: Harmonize,
+        /// Offer to update each album
+        #[clap(long)]
+        apply: bool,
+        /// Update without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Keep the lossless copies of each recording in the archive and exactly one lossy copy in
+    /// the mirror. Only recordings with a lossless copy in the archive are looked at: their lossy
+    /// copies in the archive, lossless copies in the mirror and extra lossy copies are strays
+    Mirror {
+        /// Archive library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Root of the portable mirror
+        #[clap(long, value_name = "PATH")]
+        mirror: PathBuf,
+        /// Offer to remove the strays of each album. A lossy copy in the archive is moved to the
+        /// same place in the mirror instead when the mirror has none
+        #[clap(long)]
+        apply: bool,
+        /// Remove without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum ArtistsAction {
+    /// Merge the folders of one artist, such as "Artist", "artist" and "Artist feat. X", into a
+    /// single folder. Files are never overwritten: those in the way are left where they are
+    Consolidate {
+        /// Music library path, defaults to the profile's library. Its subfolders are taken as
+        /// artist folders
+        library_path: Option<PathBuf>,
+        /// Offer to merge the folders of each artist
+        #[clap(long)]
+        apply: bool,
+        /// Merge without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum LyricsAction {
+    /// Find tracks whose embedded and sidecar lyrics differ, or with both "Song.lrc" and
+    /// "Song.flac.lrc", and keep one version. Synced lyrics win over plain ones
+    Reconcile {
+        /// Music library path, defaults to the profile's library. Use "-" to read a newline or
+        /// NUL separated list of files from stdin instead
+        library_path: Option<PathBuf>,
+        /// Only look at tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Offer to pick the version to keep for each track
+        #[clap(long)]
+        apply: bool,
+        /// Keep synced over plain lyrics without asking, implies --apply. Other conflicts are
+        /// skipped
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Share of tracks with synced, plain or no lyrics, embedded or in a sidecar file, by
+    /// artist or genre. Groups missing the most lyrics come first
+    Coverage {
+        /// Music library path, defaults to the profile's library. Use "-" to read a newline or
+        /// NUL separated list of files from stdin instead
+        library_path: Option<PathBuf>,
+        /// Only count tracks matching this expression, e.g. "year >= 2000"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Group tracks by
+        #[clap(long, value_enum, default_value_t = CoverageGroup::Artist)]
+        by: CoverageGroup,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum CoverageGroup {
+    /// Main album artist, or track artist
+    Artist,
+    Genre,
+}
+
+#[derive(clap::Subcommand)]
+pub enum BackupAction {
+    /// Write the size and checksum of every file of the library, covers and lyrics included
+    Manifest {
+        /// Music library path, defaults to the profile's library. Use "-" to read a newline or
+        /// NUL separated list of files from stdin instead
+        library_path: Option<PathBuf>,
+        /// Write the manifest to this file instead of stdout
+        #[clap(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Compare the library with a manifest and list missing, changed and extra files. Exits
+    /// with 2 when they differ
+    Verify {
+        /// Manifest written by "backup manifest"
+        manifest: PathBuf,
+        /// Music library path, defaults to the profile's library. Use "-" to only compare the
+        /// files listed on stdin, newline or NUL separated and relative to the library folder
+        library_path: Option<PathBuf>,
+        /// Only hash this percentage of the files, those verified the longest time ago, e.g. 5
+        /// from a daily job to go through the whole library every 20 days. Missing, extra and
+        /// resized files are still found among all files
+        #[clap(long, value_name = "PERCENT", value_parser = clap::value_parser!(u8).range(1..=100))]
+        portion: Option<u8>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum ReportAction {
+    /// Static HTML gallery of the albums by artist, with covers, quality badges and missing art
+    /// or lyrics warnings
+    Html {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Folder to write index.html and the covers to
+        #[clap(short, long, default_value = "report")]
+        output: PathBuf,
+    },
+
+    /// Tracks whose paths are too long or too deep for portable players, many of which give up
+    /// past 255 characters or 8 folders
+    Paths {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Longest path allowed, in characters, counted from the library folder
+        #[clap(long, default_value_t = 255)]
+        max_length: usize,
+        /// Most folders allowed above a track, counted from the library folder
+        #[clap(long, default_value_t = 8)]
+        max_depth: usize,
+        /// Folder the library is copied to on the device, e.g. "Music", counted in both limits
+        #[clap(long)]
+        prefix: Option<PathBuf>,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum TagAction {
+    /// Make track numbers consistent within each album ("1/12" vs "01") and recompute track
+    /// totals per disc
+    Renumber {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Only renumber albums with a track matching this expression, e.g. "artist = Low"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Zero-pad written track numbers and renamed files to this many digits. Some formats,
+        /// like Vorbis comments, are always read back unpadded
+        #[clap(long, default_value_t = 2)]
+        pad: usize,
+        /// Renumber 1..N in the current order, closing gaps and duplicates
+        #[clap(long)]
+        sequential: bool,
+        /// Also rename files so their leading number matches the tag
+        #[clap(long)]
+        rename: bool,
+        /// Rewrite every track, not only those with wrong numbers, to normalize how numbers are
+        /// stored
+        #[clap(long)]
+        all: bool,
+        /// Write the tag changes as a diff to this file, or "-" for stdout, to review them and
+        /// apply them later with "tag apply". Renames are not part of the diff
+        #[clap(long, value_name = "FILE")]
+        diff: Option<PathBuf>,
+        /// Offer to apply the changes of each album
+        #[clap(long)]
+        apply: bool,
+        /// Apply without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Fill in missing ISRCs from MusicBrainz, for tracks tagged with a MusicBrainz recording ID
+    /// as Picard does
+    Isrc {
+        /// Music library path, defaults to the profile's library. Use "-" to read a newline or
+        /// NUL separated list of files from stdin instead
+        library_path: Option<PathBuf>,
+        /// Only look at tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Write the tag changes as a diff to this file, or "-" for stdout, to review them and
+        /// apply them later with "tag apply"
+        #[clap(long, value_name = "FILE")]
+        diff: Option<PathBuf>,
+        /// Offer to write the ISRC of each track
+        #[clap(long)]
+        apply: bool,
+        /// Write without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Remove junk fields such as comments or encoder info from every track
+    Strip {
+        /// Music library path, defaults to the profile's library. Use "-" to read a newline or
+        /// NUL separated list of files from stdin instead
+        library_path: Option<PathBuf>,
+        /// Only strip tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Comma separated keys to remove, e.g. comment,encoder,ripping-tool. Defaults to
+        /// tag.strip_keys from the config
+        #[clap(long, value_delimiter = ',')]
+        keys: Option<Vec<String>>,
+        /// Also remove every non-standard field (unknown Vorbis fields, TXXX and private ID3v2
+        /// frames)
+        #[clap(long)]
+        private_frames: bool,
+        /// Write the tag changes as a diff to this file, or "-" for stdout, to review them and
+        /// apply them later with "tag apply"
+        #[clap(long, value_name = "FILE")]
+        diff: Option<PathBuf>,
+        /// Offer to strip the listed fields
+        #[clap(long)]
+        apply: bool,
+        /// Strip without asking for confirmation, implies --apply
+        #[clap(short, long)]
+        yes: bool,
+    },
+
+    /// Apply a diff written with --diff, possibly edited. Files whose tags changed since the diff
+    /// was written are left alone
+    Apply {
+        /// Diff file
+        diff: PathBuf,
+        /// Apply without asking for confirmation
+        #[clap(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(clap::Subcommand)]
+pub enum PlaylistAction {
+    /// Anniversary playlist: tracks released this month in previous years, or added to the
+    /// library this month some years ago
+    OnThisDay {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Only pick tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        #[clap(long, value_enum, default_value_t = OnThisDay::Released)]
+        mode: OnThisDay,
+        /// With --mode added, how many years back to look
+        #[clap(long, default_value_t = 1)]
+        years_ago: u32,
+        #[clap(flatten)]
+        output: PlaylistOutput,
+    },
+
+    /// Random playlist that never plays the same artist twice in a row
+    Sample {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Only pick tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Maximum number of tracks
+        #[clap(long, default_value_t = 100)]
+        max: usize,
+        /// Maximum number of tracks by the same artist
+        #[clap(long)]
+        per_artist: Option<usize>,
+        /// Only pick tracks of this genre (case insensitive)
+        #[clap(long)]
+        genre: Option<String>,
+        /// Only pick tracks with this value in a field other than the usual ones, e.g.
+        /// --tag mood=calm. Can be repeated, all must match
+        #[clap(long = "tag", value_name = "FIELD=VALUE", value_parser = parse_key_value)]
+        tags: Vec<(String, String)>,
+        /// Maximum total duration, in minutes
+        #[clap(long)]
+        duration: Option<u32>,
+        /// Seed for the shuffle, to get the same playlist again
+        #[clap(long)]
+        seed: Option<u64>,
+        #[clap(flatten)]
+        output: PlaylistOutput,
+    },
+
+    /// Rotation playlist mixing tracks added recently with the back catalog, where tracks are
+    /// picked more often the longer ago a rotation last picked them. Run it regularly, e.g. from
+    /// cron, to refresh the playlist
+    Rotation {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Only pick tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Number of tracks
+        #[clap(long, default_value_t = 50)]
+        count: usize,
+        /// Share of the tracks taken from recent additions, from 0 to 1
+        #[clap(long, default_value_t = 0.3, value_parser = parse_share)]
+        recent_share: f64,
+        /// Tracks first seen by a scan in the last days are recent additions
+        #[clap(long, default_value_t = 30)]
+        recent_days: u64,
+        /// Days after which a track picked by a rotation is half as likely to be picked again as
+        /// one never picked
+        #[clap(long, default_value_t = 90)]
+        half_life: u64,
+        /// Seed for the picks, to get the same playlist again
+        #[clap(long)]
+        seed: Option<u64>,
+        #[clap(flatten)]
+        output: PlaylistOutput,
+    },
+
+    /// "More like this": the tracks closest to a given one by tempo, loudness, genre, year and
+    /// artist, from the BPM, ReplayGain and usual tags. Tracks missing a tag the given one has
+    /// rank lower
+    Similar {
+        /// Track to start from, in the library or not
+        file: PathBuf,
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Only pick tracks matching this expression, e.g. "genre = jazz and year < 1970"
+        #[clap(long = "where", value_name = "EXPR")]
+        filter: Option<Filter>,
+        /// Number of tracks after the given one
+        #[clap(long, default_value_t = 25)]
+        count: usize,
+        #[clap(flatten)]
+        output: PlaylistOutput,
+    },
+
+    /// Write a CUE sheet next to the tracks of every album, named after the album
+    Cue {
+        /// Music library path, defaults to the profile's library
+        library_path: Option<PathBuf>,
+        /// Index all tracks of an album in this file of the album folder (e.g. a disc image) from
+        /// their durations instead of referencing each track file
+        #[clap(long)]
+        single_file: Option<PathBuf>,
+        /// Overwrite existing CUE sheets
+        #[clap(long)]
+        force: bool,
+        /// Export profile from the config for line endings and separators, paths are always
+        /// relative to the album folder
+        #[clap(long)]
+        export: Option<String>,
+    },
+}
+
+fn parse_key_value(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .ok_or_else(|| format!("expected FIELD=VALUE, got \"{}\"", value))
+}
+
+fn parse_share(value: &str) -> Result<f64, String> {
+    value
+        .parse::<f64>()
+        .ok()
+        .filter(|share| (0.0..=1.0).contains(share))
+        .ok_or_else(|| format!("expected a number from 0 to 1, got \"{}\"", value))
+}
+
+#[derive(clap::Args)]
+pub struct PlaylistOutput {
+    /// Write the playlist to this file instead of stdout
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+    #[clap(long, value_enum, default_value_t = PlaylistFormat::M3u)]
+    pub format: PlaylistFormat,
+    /// For CUE sheets, index all tracks in this single file (e.g. a disc image) from their
+    /// durations instead of referencing each track file
+    #[clap(long)]
+    pub single_file: Option<PathBuf>,
+    /// Export profile from the config, for players with other conventions. "windows" is built
+    /// in: CRLF line endings, backslashes, a BOM and relative paths
+    #[clap(long)]
+    pub export: Option<String>,
+    /// Copy the cover of each album of the playlist into this folder, under the path of the album
+    /// folder inside the library, as "cover.jpg" or "cover.png" for players that look for one
+    #[clap(long, value_name = "DIR")]
+    pub covers: Option<PathBuf>,
+    /// Also copy the cover of the album with the most tracks next to the playlist, named after
+    /// it, which some players show for M3U playlists
+    #[clap(long, requires = "output")]
+    pub playlist_cover: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum PlaylistFormat {
+    /// Extended M3U
+    M3u,
+    /// CUE sheet
+    Cue,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum Harmonize {
+    /// The value most tracks have, the first track's on ties
+    Majority,
+    /// The value of the first track, by disc and track number
+    First,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy)]
+pub enum OnThisDay {
+    /// Released in the current month of a previous year
+    Released,
+    /// First scanned in the current month, some years ago
+    Added,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_share_accepts_0_to_1() {
+        assert_eq!(parse_share("0"), Ok(0.0));
+        assert_eq!(parse_share("0.3"), Ok(0.3));
+        assert_eq!(parse_share("1"), Ok(1.0));
+        assert!(parse_share("1.5").is_err());
+        assert!(parse_share("-0.1").is_err());
+        assert!(parse_share("half").is_err());
+    }
 }
@@ -0,0 +1,989 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use lofty::{
+    picture::PictureType,
+    tag::{ItemKey, Tag},
+};
+use log::error;
+use serde::Serialize;
+
+use crate::{
+    AUDIO_EXTENSIONS, Status,
+    album::{
+        ReleaseType, album_artist, album_country, base_title, editions, folder_cover, release_type,
+    },
+    artist::main_artist,
+    cli::{AlbumsAction, Harmonize},
+    commands::{check_library_root, library_folder, load_library},
+    config::Profile,
+    fs::{delete_file, move_file, write_file},
+    hooks::{Event, Hooks},
+    image::image_info,
+    journal,
+    library::group_by_album,
+    output::{confirm, say},
+    summary::Summary,
+    tag::{edit_tag, embedded_cover, pictures_digest},
+    track::DirtyTrack,
+};
+
+pub fn run(
+    action: AlbumsAction,
+    profile: &Profile,
+    hooks: &Hooks,
+    force_root: bool,
+    summary: &mut Summary,
+) -> Status {
+    match action {
+        AlbumsAction::Reconcile {
+            library_path,
+            apply,
+            yes,
+        } => reconcile(library_path, apply || yes, yes, profile, hooks, summary),
+        AlbumsAction::Consistency {
+            library_path,
+            prefer,
+            apply,
+            yes,
+        } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            consistency(&library.tracks, prefer, apply || yes, yes, summary)
+        }
+        AlbumsAction::Mirror {
+            library_path,
+            mirror: mirror_path,
+            apply,
+            yes,
+        } => {
+            // The root guard of `crate::run` only sees the archive, strays are deleted from the
+            // mirror
+            if (apply || yes)
+                && !force_root
+                && let Err(reason) = check_library_root(&mirror_path, &profile.extensions())
+            {
+                error!(
+                    "Refusing to modify {}: {}. Pass --force-root if this is really your mirror",
+                    mirror_path.display(),
+                    reason
+                );
+                return Status::Failure;
+            }
+            mirror(
+                library_path,
+                mirror_path,
+                apply || yes,
+                yes,
+                profile,
+                hooks,
+                summary,
+            )
+        }
+        AlbumsAction::Art {
+            library_path,
+            strip,
+            min_size,
+            apply,
+            yes,
+        } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            art(
+                &library.tracks,
+                min_size,
+                strip || apply || yes,
+                apply || yes,
+                yes,
+                summary,
+            )
+        }
+        AlbumsAction::List { library_path, json } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            list(&library.tracks, json, summary)
+        }
+        AlbumsAction::MissingArt {
+            library_path,
+            folders,
+        } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            missing_art(&library.tracks, folders, summary)
+        }
+        AlbumsAction::Compilations {
+            library_path,
+            min_artists,
+            album_artist,
+            apply,
+            yes,
+        } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            compilations(
+                &library.tracks,
+                min_artists,
+                &album_artist,
+                apply || yes,
+                yes,
+                summary,
+            )
+        }
+    }
+}
+
+/// Find folders whose tracks carry variants of the same album tag and offer to unify them.
+fn reconcile(
+    library_path: Option<PathBuf>,
+    apply: bool,
+    yes: bool,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Status {
+    let library = match load_library(library_path, profile, hooks, summary) {
+        Ok(library) => library,
+        Err(status) => return status,
+    };
+
+    let mut folders: BTreeMap<&Path, Vec<&DirtyTrack>> = BTreeMap::new();
+    for track in &library.tracks {
+        if let Some(folder) = track.file_path.as_deref().and_then(Path::parent) {
+            folders.entry(folder).or_default().push(track);
+        }
+    }
+
+    let mut failed = 0;
+    for (folder, tracks) in &folders {
+        let mut titles: BTreeMap<&str, usize> = BTreeMap::new();
+        for album in tracks.iter().filter_map(|t| t.album.as_deref()) {
+            *titles.entry(album).or_default() += 1;
+        }
+        if titles.len() < 2 {
+            continue;
+        }
+        // Different base titles means the folder really holds several albums
+        let bases: BTreeSet<String> = titles
+            .keys()
+            .map(|t| base_title(t).to_lowercase())
+            .collect();
+        if bases.len() != 1 {
+            continue;
+        }
+
+        let Some(most_common) = titles.iter().max_by_key(|(_, count)| **count) else {
+            continue;
+        };
+        let target = base_title(most_common.0).to_string();
+        summary.matched += 1;
+
+        say!("{}", folder.display());
+        for (title, count) in &titles {
+            say!("  {} ({} tracks)", title, count);
+        }
+        say!("  -> {}", target);
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm(&format!("Set the album of all tracks to \"{}\"?", target)) {
+            summary.skipped += 1;
+            continue;
+        }
+
+        for track in tracks {
+            let (Some(path), Some(album)) = (&track.file_path, &track.album) else {
+                continue;
+            };
+            if *album == target {
+                continue;
+            }
+            match edit_tag(path, |tag| {
+                tag.insert_text(ItemKey::AlbumTitle, target.clone())
+            }) {
+                Ok(_) => summary.modified += 1,
+                Err(e) => {
+                    error!("Could not update {}: {}", path.display(), e);
+                    summary.errors += 1;
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("No split albums found");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Report albums whose tracks embed different covers, or only some of them one, and albums whose
+/// cover is smaller than `min_size` pixels. With `strip`, albums embedding the same cover in every
+/// track are listed too, with the space that removing the copies would save; the cover is kept as
+/// a folder image.
+fn art(
+    tracks: &[DirtyTrack],
+    min_size: u32,
+    strip: bool,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let mut failed = 0;
+    for ((folder, album), tracks) in group_by_album(tracks) {
+        let with_art: Vec<(&Path, (u64, u64))> = tracks
+            .iter()
+            .filter(|t| t.embedded_cover)
+            .filter_map(|t| {
+                let path = t.file_path.as_deref()?;
+                Some((path, pictures_digest(path)?))
+            })
+            .collect();
+        if with_art.is_empty() {
+            continue;
+        }
+        let covers: BTreeSet<u64> = with_art.iter().map(|(_, (digest, _))| *digest).collect();
+        let without_art = tracks.len() - with_art.len();
+
+        if covers.len() > 1 || without_art > 0 {
+            summary.matched += 1;
+            say!(
+                "{} ({}): {} different covers, {} of {} tracks without art",
+                album,
+                folder.display(),
+                covers.len(),
+                without_art,
+                tracks.len()
+            );
+            continue;
+        }
+
+        // Only the header of the cover is parsed, to keep the audit fast on large libraries
+        let small_cover = embedded_cover(with_art[0].0)
+            .and_then(|(data, _)| image_info(&data))
+            .filter(|info| info.is_smaller_than(min_size));
+        if let Some(info) = small_cover {
+            summary.matched += 1;
+            say!("{} ({}): small cover, {}", album, folder.display(), info);
+        }
+        if !strip {
+            continue;
+        }
+
+        if small_cover.is_none() {
+            summary.matched += 1;
+        }
+        let size: usize = with_art
+            .iter()
+            .filter_map(|(path, _)| embedded_cover(path))
+            .map(|(data, _)| data.len())
+            .sum();
+        let folder_image = folder_cover(folder);
+        say!(
+            "{} ({}): same cover in {} tracks, {:.1} MB to strip{}",
+            album,
+            folder.display(),
+            with_art.len(),
+            size as f64 / 1_000_000.0,
+            if folder_image.is_some() {
+                ""
+            } else {
+                ", folder.jpg to write"
+            }
+        );
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm("Strip the embedded art?") {
+            summary.skipped += 1;
+            continue;
+        }
+
+        if folder_image.is_none()
+            && let Err(e) = write_folder_cover(folder, with_art[0].0)
+        {
+            error!("Could not write the cover of {}: {}", folder.display(), e);
+            summary.errors += 1;
+            failed += 1;
+            continue;
+        }
+        for (path, _) in with_art {
+            let size_before = fs::metadata(path).map_or(0, |m| m.len());
+            // Only the front cover goes, or an untyped picture standing for it: back covers,
+            // booklet pages and the like stay, also on later runs
+            let result = edit_tag(path, |tag| {
+                let position = |kind: PictureType| {
+                    tag.pictures()
+                        .iter()
+                        .position(|picture| picture.pic_type() == kind)
+                };
+                let Some(index) =
+                    position(PictureType::CoverFront).or_else(|| position(PictureType::Other))
+                else {
+                    return false;
+                };
+                tag.remove_picture(index);
+                true
+            });
+            match result {
+                Ok(false) => {}
+                Ok(true) => {
+                    summary.modified += 1;
+                    let size_after = fs::metadata(path).map_or(0, |m| m.len());
+                    summary.bytes_freed += size_before.saturating_sub(size_after);
+                }
+                Err(e) => {
+                    error!("Could not update {}: {}", path.display(), e);
+                    summary.errors += 1;
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if summary.bytes_freed > 0 {
+        say!("Saved {:.1} MB", summary.bytes_freed as f64 / 1_000_000.0);
+    }
+    if summary.matched == 0 {
+        say!("Embedded art is consistent and large enough in every album");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// One album of `albums list`.
+#[derive(Serialize)]
+struct AlbumEntry<'a> {
+    artist: &'a str,
+    album: &'a str,
+    year: Option<u32>,
+    /// Release country, or the country shared by the ISRCs.
+    country: Option<String>,
+    release_type: ReleaseType,
+    editions: Vec<&'static str>,
+    tracks: usize,
+    /// Total size of the files in bytes.
+    size: u64,
+    folder: &'a Path,
+}
+
+/// List the albums by artist and year, with their countries, release types, editions, track counts
+/// and sizes. With `json` the list is printed as a JSON array, even with `--quiet`.
+fn list(tracks: &[DirtyTrack], json: bool, summary: &mut Summary) -> Status {
+    let mut albums: Vec<AlbumEntry> = group_by_album(tracks)
+        .into_iter()
+        .map(|((folder, album), tracks)| AlbumEntry {
+            artist: album_artist(&tracks),
+            album,
+            year: tracks.iter().find_map(|t| t.year),
+            country: album_country(&tracks),
+            release_type: release_type(album, &tracks),
+            editions: editions(album),
+            tracks: tracks.len(),
+            size: tracks
+                .iter()
+                .filter_map(|t| fs::metadata(t.file_path.as_deref()?).ok())
+                .map(|m| m.len())
+                .sum(),
+            folder,
+        })
+        .collect();
+    albums.sort_by(|a, b| {
+        (a.artist.to_lowercase(), a.year, a.album).cmp(&(b.artist.to_lowercase(), b.year, b.album))
+    });
+    summary.matched = albums.len();
+
+    if json {
+        match serde_json::to_string_pretty(&albums) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                error!("Could not serialize the album list: {}", e);
+                return Status::Failure;
+            }
+        }
+    } else {
+        let mut artist = None;
+        for album in &albums {
+            if artist != Some(album.artist) {
+                say!("{}", album.artist);
+                artist = Some(album.artist);
+            }
+            let mut line = format!("  {}", album.album);
+            match (album.year, &album.country) {
+                (Some(year), Some(country)) => line.push_str(&format!(" ({}, {})", year, country)),
+                (Some(year), None) => line.push_str(&format!(" ({})", year)),
+                (None, Some(country)) => line.push_str(&format!(" ({})", country)),
+                (None, None) => {}
+            }
+            if !album.editions.is_empty() {
+                line.push_str(&format!(" [{}]", album.editions.join(", ")));
+            }
+            say!(
+                "{} - {}, {} tracks, {:.1} MB",
+                line,
+                album.release_type.label(),
+                album.tracks,
+                album.size as f64 / 1_000_000.0
+            );
+        }
+    }
+
+    if albums.is_empty() {
+        say!("No album found");
+        Status::NothingMatched
+    } else {
+        Status::Ok
+    }
+}
+
+/// List albums without any cover, embedded or in their folder. With `folders_only` only the
+/// folders are printed, even with `--quiet`, so they can be piped to another tool.
+fn missing_art(tracks: &[DirtyTrack], folders_only: bool, summary: &mut Summary) -> Status {
+    for ((folder, album), tracks) in group_by_album(tracks) {
+        if tracks.iter().any(|t| t.embedded_cover) || folder_cover(folder).is_some() {
+            continue;
+        }
+        summary.matched += 1;
+        if folders_only {
+            println!("{}", folder.display());
+        } else {
+            say!(
+                "{} - {} ({})",
+                album_artist(&tracks),
+                album,
+                folder.display()
+            );
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("Every album has cover art");
+        Status::NothingMatched
+    } else {
+        Status::Ok
+    }
+}
+
+/// Save the cover embedded in `track` as the folder image of the album.
+fn write_folder_cover(folder: &Path, track: &Path) -> Result<(), String> {
+    let (data, extension) =
+        embedded_cover(track).ok_or_else(|| "no embedded cover to extract".to_string())?;
+    let path = folder.join(format!("folder.{}", extension));
+    write_file(&path, |out| out.write_all(&data)).map_err(|e| e.to_string())?;
+    journal::record_write(&path, None, None);
+    Ok(())
+}
+
+/// Flag albums with many different track artists as compilations, with a common album artist so
+/// players list them once.
+fn compilations(
+    tracks: &[DirtyTrack],
+    min_artists: usize,
+    album_artist: &str,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let albums = group_by_album(tracks);
+
+    let mut failed = 0;
+    for ((folder, album), tracks) in albums {
+        let artists: BTreeSet<String> = tracks
+            .iter()
+            .filter_map(|t| t.artist.as_deref())
+            .map(|artist| main_artist(artist).to_lowercase())
+            .collect();
+        if artists.len() < min_artists {
+            continue;
+        }
+
+        let to_fix: Vec<&Path> = tracks
+            .iter()
+            .filter(|t| !t.compilation || t.album_artist.as_deref() != Some(album_artist))
+            .filter_map(|t| t.file_path.as_deref())
+            .collect();
+        if to_fix.is_empty() {
+            continue;
+        }
+        summary.matched += 1;
+
+        say!(
+            "{} ({}): {} artists, {} of {} tracks to update",
+            album,
+            folder.display(),
+            artists.len(),
+            to_fix.len(),
+            tracks.len()
+        );
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm("Mark as compilation?") {
+            summary.skipped += 1;
+            continue;
+        }
+
+        for path in to_fix {
+            let result = edit_tag(path, |tag| {
+                tag.insert_text(ItemKey::FlagCompilation, "1".to_string());
+                tag.insert_text(ItemKey::AlbumArtist, album_artist.to_string());
+                true
+            });
+            match result {
+                Ok(_) => summary.modified += 1,
+                Err(e) => {
+                    error!("Could not update {}: {}", path.display(), e);
+                    summary.errors += 1;
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("No compilation to fix");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Fields a date can be read from, see `DirtyTrack::date`.
+const DATE_KEYS: [ItemKey; 3] = [ItemKey::ReleaseDate, ItemKey::RecordingDate, ItemKey::Year];
+
+/// Fields every track of an album should agree on.
+#[derive(Clone, Copy)]
+enum AlbumField {
+    Album,
+    AlbumArtist,
+    Year,
+    Genre,
+}
+
+impl AlbumField {
+    const ALL: [AlbumField; 4] = [
+        AlbumField::Album,
+        AlbumField::AlbumArtist,
+        AlbumField::Year,
+        AlbumField::Genre,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            AlbumField::Album => "album",
+            AlbumField::AlbumArtist => "album artist",
+            AlbumField::Year => "year",
+            AlbumField::Genre => "genre",
+        }
+    }
+
+    /// Value compared across the tracks.
+    fn value(self, track: &DirtyTrack) -> Option<String> {
+        match self {
+            AlbumField::Album => track.album.clone(),
+            AlbumField::AlbumArtist => track.album_artist.clone(),
+            AlbumField::Year => track.year.map(|year| year.to_string()),
+            AlbumField::Genre => track.genre.clone(),
+        }
+    }
+
+    /// Write the field. The year is written as a full date, to every date field the tag already
+    /// has so that none of them disagrees.
+    fn set(self, tag: &mut Tag, value: &str) {
+        let key = match self {
+            AlbumField::Album => ItemKey::AlbumTitle,
+            AlbumField::AlbumArtist => ItemKey::AlbumArtist,
+            AlbumField::Genre => ItemKey::Genre,
+            AlbumField::Year => {
+                let keys: Vec<ItemKey> = DATE_KEYS
+                    .into_iter()
+                    .filter(|key| tag.get_string(key).is_some())
+                    .collect();
+                if keys.is_empty() {
+                    tag.insert_text(ItemKey::RecordingDate, value.to_string());
+                }
+                for key in keys {
+                    let value = if key == ItemKey::Year {
+                        value.get(..4).unwrap_or(value)
+                    } else {
+                        value
+                    };
+                    tag.insert_text(key, value.to_string());
+                }
+                return;
+            }
+        };
+        tag.insert_text(key, value.to_string());
+    }
+}
+
+/// Report albums whose tracks disagree on album level fields and offer to give them all the
+/// value picked by `prefer`. Tracks are grouped by folder and album title regardless of case, so
+/// titles that differ otherwise are left to `reconcile`.
+fn consistency(
+    tracks: &[DirtyTrack],
+    prefer: Harmonize,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let mut albums: BTreeMap<(&Path, String), Vec<&DirtyTrack>> = BTreeMap::new();
+    for track in tracks {
+        if let (Some(folder), Some(album)) = (
+            track.file_path.as_deref().and_then(Path::parent),
+            track.album.as_deref(),
+        ) {
+            albums
+                .entry((folder, album.trim().to_lowercase()))
+                .or_default()
+                .push(track);
+        }
+    }
+
+    let mut failed = 0;
+    for ((folder, _), mut tracks) in albums {
+        tracks.sort_by_key(|t| {
+            (
+                t.disc_number.unwrap_or(1),
+                t.track_number.unwrap_or(u32::MAX),
+                t.file_path.clone(),
+            )
+        });
+
+        // Field, value compared and value written
+        let mut fixes: Vec<(AlbumField, String, String)> = Vec::new();
+        let mut lines = Vec::new();
+        for field in AlbumField::ALL {
+            let counts = tally(tracks.iter().map(|track| field.value(track)));
+            if counts.len() < 2 {
+                continue;
+            }
+            let Some(target) = harmonized(&counts, prefer).cloned() else {
+                continue;
+            };
+            let written = match field {
+                AlbumField::Year => tracks
+                    .iter()
+                    .find(|track| field.value(track).as_ref() == Some(&target))
+                    .and_then(|track| track.date.clone())
+                    .unwrap_or_else(|| target.clone()),
+                _ => target.clone(),
+            };
+            lines.push(format!(
+                "  {}: {} -> {}",
+                field.name(),
+                counts
+                    .iter()
+                    .map(|(value, count)| format!(
+                        "{} ({})",
+                        value.as_deref().unwrap_or("none"),
+                        count
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                written
+            ));
+            fixes.push((field, target, written));
+        }
+        if fixes.is_empty() {
+            continue;
+        }
+        summary.matched += 1;
+
+        say!(
+            "{} ({})",
+            tracks[0].album.as_deref().unwrap_or_default(),
+            folder.display()
+        );
+        for line in &lines {
+            say!("{}", line);
+        }
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm("Harmonize these fields?") {
+            summary.skipped += 1;
+            continue;
+        }
+
+        for track in &tracks {
+            let Some(path) = &track.file_path else {
+                continue;
+            };
+            let changes: Vec<&(AlbumField, String, String)> = fixes
+                .iter()
+                .filter(|(field, target, _)| field.value(track).as_ref() != Some(target))
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+            let result = edit_tag(path, |tag| {
+                for (field, _, written) in &changes {
+                    field.set(tag, written);
+                }
+                true
+            });
+            match result {
+                Ok(_) => summary.modified += 1,
+                Err(e) => {
+                    error!("Could not update {}: {}", path.display(), e);
+                    summary.errors += 1;
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("Every album is consistent");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Value to give every track, from the values of `tally`. Missing values are never picked.
+fn harmonized(counts: &[(Option<String>, usize)], prefer: Harmonize) -> Option<&String> {
+    let mut best: Option<(&String, usize)> = None;
+    for (value, count) in counts {
+        let Some(value) = value else {
+            continue;
+        };
+        match prefer {
+            Harmonize::First => return Some(value),
+            // Values come in order of first appearance, the earliest wins ties
+            Harmonize::Majority if best.is_none_or(|(_, best)| *count > best) => {
+                best = Some((value, *count))
+            }
+            Harmonize::Majority => {}
+        }
+    }
+    best.map(|(value, _)| value)
+}
+
+/// Distinct values with their number of occurrences, in order of first appearance.
+fn tally(values: impl Iterator<Item = Option<String>>) -> Vec<(Option<String>, usize)> {
+    let mut counts: Vec<(Option<String>, usize)> = Vec::new();
+    for value in values {
+        match counts.iter_mut().find(|(known, _)| *known == value) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((value, 1)),
+        }
+    }
+    counts
+}
+
+/// What the mirror policy does with a stray copy of a recording.
+enum Stray {
+    /// Remove it, for this reason.
+    Remove(&'static str),
+    /// Move the archive's lossy copy here, the mirror having none.
+    Move(PathBuf),
+}
+
+/// Keep the lossless copies of each archived recording in the archive and a single lossy copy, the
+/// one with the highest bitrate, in the mirror. Strays are listed by album folder.
+fn mirror(
+    library_path: Option<PathBuf>,
+    mirror_path: PathBuf,
+    apply: bool,
+    yes: bool,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Status {
+    let Some(archive_path) = library_folder(profile, library_path) else {
+        return Status::Failure;
+    };
+    if !mirror_path.is_dir() {
+        error!("{} is not a folder", mirror_path.display());
+        return Status::Failure;
+    }
+    if let (Ok(archive), Ok(mirror)) = (archive_path.canonicalize(), mirror_path.canonicalize())
+        && (archive.starts_with(&mirror) || mirror.starts_with(&archive))
+    {
+        error!("The archive and the mirror must not contain one another");
+        return Status::Failure;
+    }
+
+    // Both lossless and lossy copies are needed, whatever the profile scans
+    let profile = Profile {
+        extensions: Some(AUDIO_EXTENSIONS.iter().map(|e| e.to_string()).collect()),
+        ..profile.clone()
+    };
+    let archive = match load_library(Some(archive_path), &profile, hooks, summary) {
+        Ok(library) => library,
+        Err(status) => return status,
+    };
+    let mirror = match load_library(Some(mirror_path), &profile, hooks, summary) {
+        Ok(library) => library,
+        Err(status) => return status,
+    };
+
+    let mut recordings: BTreeMap<String, Vec<(&DirtyTrack, bool)>> = BTreeMap::new();
+    for (tracks, in_mirror) in [(&archive.tracks, false), (&mirror.tracks, true)] {
+        for track in tracks {
+            if let Some(key) = recording_key(track) {
+                recordings.entry(key).or_default().push((track, in_mirror));
+            }
+        }
+    }
+
+    let mut strays: BTreeMap<&Path, Vec<(&Path, Stray)>> = BTreeMap::new();
+    let mut unmirrored = 0;
+    for copies in recordings.values() {
+        if !copies
+            .iter()
+            .any(|(track, in_mirror)| !in_mirror && is_lossless(track))
+        {
+            continue;
+        }
+        let mut mirrored: Vec<&DirtyTrack> = copies
+            .iter()
+            .filter(|(track, in_mirror)| *in_mirror && !is_lossless(track))
+            .map(|(track, _)| *track)
+            .collect();
+        mirrored.sort_by_key(|track| (Reverse(track.bitrate), track.file_path.clone()));
+        let mut kept = mirrored
+            .first()
+            .and_then(|track| track.file_path.as_deref());
+
+        for (track, in_mirror) in copies {
+            let Some(path) = track.file_path.as_deref() else {
+                continue;
+            };
+            let stray = match (in_mirror, is_lossless(track)) {
+                (false, true) => continue,
+                (true, true) => Stray::Remove("lossless copy in the mirror"),
+                (true, false) if kept == Some(path) => continue,
+                (true, false) => Stray::Remove("extra lossy copy in the mirror"),
+                (false, false) => {
+                    let target = path
+                        .strip_prefix(&archive.path)
+                        .map(|relative| mirror.path.join(relative));
+                    match target {
+                        Ok(target) if kept.is_none() && !target.exists() => {
+                            kept = Some(path);
+                            Stray::Move(target)
+                        }
+                        _ => Stray::Remove("lossy copy in the archive"),
+                    }
+                }
+            };
+            if let Some(folder) = path.parent() {
+                strays.entry(folder).or_default().push((path, stray));
+            }
+        }
+        if kept.is_none() {
+            unmirrored += 1;
+        }
+    }
+
+    let mut failed = 0;
+    for (folder, strays) in &strays {
+        summary.matched += 1;
+        say!("{}", folder.display());
+        for (path, stray) in strays {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            match stray {
+                Stray::Remove(reason) => say!("  remove {} ({})", name, reason),
+                Stray::Move(target) => say!("  move {} -> {}", name, target.display()),
+            }
+        }
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm(&format!("Apply these {} changes?", strays.len())) {
+            summary.skipped += 1;
+            continue;
+        }
+        for (path, stray) in strays {
+            let result = match stray {
+                Stray::Remove(_) => delete_file(path).map(|kept| {
+                    journal::record_delete(path, None, kept.as_deref());
+                    hooks.fire(Event::FileDeleted {
+                        path,
+                        kept: kept.as_deref(),
+                    });
+                }),
+                Stray::Move(target) => target
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| move_file(path, target))
+                    .inspect(|_| journal::record_rename(path, target)),
+            };
+            match result {
+                Ok(()) => summary.modified += 1,
+                Err(e) => {
+                    error!("Could not update {}: {}", path.display(), e);
+                    summary.errors += 1;
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if unmirrored > 0 {
+        say!(
+            "Archived recordings without a lossy copy for the mirror: {}",
+            unmirrored
+        );
+    }
+    if summary.matched == 0 {
+        say!("No stray copies found");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// What identifies one recording across its copies in different formats, from the tags since
+/// transcodes often lose the MusicBrainz IDs. Untitled tracks cannot be matched.
+fn recording_key(track: &DirtyTrack) -> Option<String> {
+    let artist = track.album_artist.as_deref().or(track.artist.as_deref())?;
+    Some(format!(
+        "{}\0{}\0{}\0{}\0{}",
+        main_artist(artist).to_lowercase(),
+        base_title(track.album.as_deref().unwrap_or_default()).to_lowercase(),
+        track.disc_number.unwrap_or(1),
+        track.track_number.unwrap_or(0),
+        track.title.as_deref()?.to_lowercase()
+    ))
+}
+
+/// Lossy codecs have no bit depth.
+fn is_lossless(track: &DirtyTrack) -> bool {
+    track.bit_depth.is_some()
+}
@@ -0,0 +1,170 @@
+use std::{
+    cmp::Reverse,
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use log::error;
+
+use crate::{
+    AUDIO_EXTENSIONS, Status,
+    artist::main_artist,
+    cli::ArtistsAction,
+    commands::library_folder,
+    config::Profile,
+    fs::recurse_directory,
+    journal,
+    output::{confirm, say},
+    summary::Summary,
+};
+
+pub fn run(action: ArtistsAction, profile: &Profile, summary: &mut Summary) -> Status {
+    match action {
+        ArtistsAction::Consolidate {
+            library_path,
+            apply,
+            yes,
+        } => {
+            let Some(library_path) = library_folder(profile, library_path) else {
+                return Status::Failure;
+            };
+            consolidate(&library_path, apply || yes, yes, summary)
+        }
+    }
+}
+
+/// An artist folder at the top of the library.
+struct ArtistFolder {
+    path: PathBuf,
+    name: String,
+    audio_files: usize,
+}
+
+/// Group the artist folders that only differ by case or featured artists, and merge each group
+/// into one folder: preferably one named after the main artist alone, then the largest.
+fn consolidate(library_path: &Path, apply: bool, yes: bool, summary: &mut Summary) -> Status {
+    let entries = match fs::read_dir(library_path) {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Could not read {}: {}", library_path.display(), e);
+            return Status::Failure;
+        }
+    };
+    let mut groups: BTreeMap<String, Vec<ArtistFolder>> = BTreeMap::new();
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let Some(name) = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        if !path.is_dir() || name.starts_with('.') {
+            continue;
+        }
+        summary.scanned += 1;
+        let audio_files =
+            recurse_directory(&path, true, Some(&|path: &PathBuf| is_audio(path)), None).len();
+        groups
+            .entry(main_artist(&name).trim().to_lowercase())
+            .or_default()
+            .push(ArtistFolder {
+                path,
+                name,
+                audio_files,
+            });
+    }
+
+    let mut failed = 0;
+    for mut folders in groups.into_values().filter(|folders| folders.len() > 1) {
+        folders.sort_by_key(|f| {
+            (
+                main_artist(&f.name) != f.name,
+                Reverse(f.audio_files),
+                f.name.clone(),
+            )
+        });
+        let (target, others) = folders.split_first().expect("groups have several folders");
+        summary.matched += 1;
+
+        say!("{} ({} audio files)", target.name, target.audio_files);
+        for folder in others {
+            say!("  <- {} ({} audio files)", folder.name, folder.audio_files);
+        }
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm("Merge these folders?") {
+            summary.skipped += 1;
+            continue;
+        }
+
+        for folder in others {
+            let mut conflicts = Vec::new();
+            if let Err(e) = merge_folder(&folder.path, &target.path, &mut conflicts) {
+                error!(
+                    "Could not merge {} into {}: {}",
+                    folder.path.display(),
+                    target.path.display(),
+                    e
+                );
+                summary.errors += 1;
+                failed += 1;
+                continue;
+            }
+            for conflict in &conflicts {
+                error!(
+                    "{} is already in {}, left in place",
+                    conflict.display(),
+                    target.name
+                );
+            }
+            summary.errors += conflicts.len();
+            failed += conflicts.len();
+            summary.modified += 1;
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("Every artist has a single folder");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+fn is_audio(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Move everything in `from` into `to`, merging the folders both have. Anything that would
+/// overwrite an existing file stays in `from` and is added to `conflicts`. `from` is removed once
+/// empty.
+fn merge_folder(from: &Path, to: &Path, conflicts: &mut Vec<PathBuf>) -> io::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let source = entry?.path();
+        let Some(name) = source.file_name() else {
+            continue;
+        };
+        let destination = to.join(name);
+        if source.is_dir() && destination.is_dir() {
+            merge_folder(&source, &destination, conflicts)?;
+        } else if destination.symlink_metadata().is_ok() {
+            conflicts.push(source);
+        } else {
+            fs::rename(&source, &destination)?;
+            journal::record_rename(&source, &destination);
+        }
+    }
+    if fs::read_dir(from)?.next().is_none() {
+        fs::remove_dir(from)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,362 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{error, warn};
+use rayon::prelude::*;
+
+use crate::{
+    Status, cancelled,
+    cli::BackupAction,
+    commands::library_folder,
+    config::Profile,
+    fs::{
+        full_hash, read_paths_from_stdin, recurse_directory, state_path, unix_now, with_io_slot,
+        write_file,
+    },
+    output::{Style, paint, say},
+    summary::Summary,
+};
+
+/// First line of manifests, lines starting with '#' are ignored when reading them back.
+const MANIFEST_HEADER: &str = "# muman backup manifest: checksum size path";
+
+/// When each file was last found to match a manifest, so that partial verifies start with the
+/// files left unchecked the longest.
+const VERIFIED_FILE: &str = "verified.txt";
+
+/// Size and checksum of a file, by path relative to the library with '/' separators.
+type Manifest = BTreeMap<String, (u64, u64)>;
+
+pub fn run(action: BackupAction, profile: &Profile, summary: &mut Summary) -> Status {
+    match action {
+        BackupAction::Manifest {
+            library_path,
+            output,
+        } => {
+            if library_path.as_deref() == Some(Path::new("-")) {
+                let Some(files) = listed_files(summary) else {
+                    return Status::Failure;
+                };
+                return manifest(Path::new("."), &files, output.as_deref(), summary);
+            }
+            let Some(library_path) = library_folder(profile, library_path) else {
+                return Status::Failure;
+            };
+            let files = library_files(&library_path, output.as_deref(), summary);
+            manifest(&library_path, &files, output.as_deref(), summary)
+        }
+        BackupAction::Verify {
+            manifest,
+            library_path,
+            portion,
+        } => {
+            if library_path.as_deref() == Some(Path::new("-")) {
+                let Some(mut files) = listed_files(summary) else {
+                    return Status::Failure;
+                };
+                let skip = std::path::absolute(&manifest).ok();
+                files.retain(|file| std::path::absolute(file).ok() != skip);
+                return verify(Path::new("."), &files, true, &manifest, portion, summary);
+            }
+            let Some(library_path) = library_folder(profile, library_path) else {
+                return Status::Failure;
+            };
+            let files = library_files(&library_path, Some(&manifest), summary);
+            verify(&library_path, &files, false, &manifest, portion, summary)
+        }
+    }
+}
+
+/// Hash `files` of the library and write the manifest to `output`, or stdout.
+fn manifest(
+    library_path: &Path,
+    files: &[PathBuf],
+    output: Option<&Path>,
+    summary: &mut Summary,
+) -> Status {
+    let hashed = summary.phase("hash", |summary| hash_files(files, summary));
+    if cancelled() {
+        error!("Cancelled, no manifest written");
+        return Status::Failure;
+    }
+    let manifest: Manifest = files
+        .iter()
+        .zip(hashed)
+        .filter_map(|(file, hashed)| Some((relative_name(library_path, file), hashed?)))
+        .collect();
+
+    let mut content = format!("{}\n", MANIFEST_HEADER);
+    for (name, (size, hash)) in &manifest {
+        content.push_str(&format!("{:016x} {} {}\n", hash, size, name));
+    }
+    match output {
+        Some(output) => {
+            if let Err(e) = write_file(output, |out| out.write_all(content.as_bytes())) {
+                error!("Could not write {}: {}", output.display(), e);
+                return Status::Failure;
+            }
+            say!(
+                "Manifest of {} files written to {}",
+                manifest.len(),
+                output.display()
+            );
+        }
+        None => print!("{}", content),
+    }
+    summary.matched = manifest.len();
+
+    if summary.errors > 0 {
+        Status::PartialFailure
+    } else if manifest.is_empty() {
+        Status::NothingMatched
+    } else {
+        Status::Ok
+    }
+}
+
+/// Compare `files` of the library with a manifest. Files of the same size are hashed to find
+/// changes, or only `portion` percent of them, those verified the longest time ago. With `listed`,
+/// `files` are only some of the library and the manifest is checked for these files alone.
+fn verify(
+    library_path: &Path,
+    files: &[PathBuf],
+    listed: bool,
+    manifest_path: &Path,
+    portion: Option<u8>,
+    summary: &mut Summary,
+) -> Status {
+    let mut expected = match read_manifest(manifest_path) {
+        Ok(expected) => expected,
+        Err(e) => {
+            error!("Could not read manifest {}: {}", manifest_path.display(), e);
+            return Status::Failure;
+        }
+    };
+    if listed {
+        let names: HashSet<String> = files
+            .iter()
+            .map(|file| relative_name(library_path, file))
+            .collect();
+        expected.retain(|name, _| names.contains(name));
+    }
+
+    let absolute_library =
+        std::path::absolute(library_path).unwrap_or_else(|_| library_path.to_path_buf());
+    let mut verified = read_verified();
+    let selected: Option<HashSet<&String>> = portion.map(|portion| {
+        let mut names: Vec<&String> = expected.keys().collect();
+        names.sort_by_key(|name| verified.get(&absolute_library.join(name)).copied());
+        let count = (names.len() * usize::from(portion)).div_ceil(100);
+        names.into_iter().take(count).collect()
+    });
+
+    let mut extra = Vec::new();
+    let mut changed = Vec::new();
+    let mut same_size = Vec::new();
+    let mut found = HashSet::new();
+    for file in files {
+        // Listed files that are gone are reported as missing below
+        if listed && !file.is_file() {
+            continue;
+        }
+        let name = relative_name(library_path, file);
+        match expected.get(&name) {
+            None => extra.push(name),
+            Some((size, _)) if fs::metadata(file).is_ok_and(|m| m.len() != *size) => {
+                changed.push(name.clone());
+                found.insert(name);
+            }
+            Some(_) => {
+                if selected
+                    .as_ref()
+                    .is_none_or(|selected| selected.contains(&name))
+                {
+                    same_size.push(file.clone());
+                }
+                found.insert(name);
+            }
+        }
+    }
+    let hashed = summary.phase("hash", |summary| hash_files(&same_size, summary));
+    if cancelled() {
+        error!("Cancelled, nothing verified");
+        return Status::Failure;
+    }
+    let now = unix_now();
+    for (file, hashed) in same_size.iter().zip(hashed) {
+        let name = relative_name(library_path, file);
+        let Some((_, hash)) = hashed else {
+            continue;
+        };
+        if expected
+            .get(&name)
+            .is_some_and(|(_, expected)| *expected != hash)
+        {
+            changed.push(name);
+        } else {
+            verified.insert(absolute_library.join(name), now);
+        }
+    }
+    if !same_size.is_empty() {
+        write_verified(&verified);
+    }
+    let missing: Vec<&String> = expected
+        .keys()
+        .filter(|name| !found.contains(*name))
+        .collect();
+
+    changed.sort();
+    extra.sort();
+    for name in &missing {
+        say!("{}  {}", paint("missing", Style::Error), name);
+    }
+    for name in &changed {
+        say!("{}  {}", paint("changed", Style::Warning), name);
+    }
+    for name in &extra {
+        say!("{}    {}", paint("extra", Style::Dim), name);
+    }
+    summary.matched = missing.len() + changed.len() + extra.len();
+    if portion.is_some() {
+        say!(
+            "Hashed {} of {} files, those verified the longest time ago",
+            same_size.len(),
+            expected.len()
+        );
+    }
+
+    if summary.matched == 0 && summary.errors == 0 {
+        if portion.is_some() {
+            say!("No differences found");
+        } else {
+            say!("All {} files match the manifest", expected.len());
+        }
+        Status::Ok
+    } else {
+        say!(
+            "{} missing, {} changed, {} extra",
+            missing.len(),
+            changed.len(),
+            extra.len()
+        );
+        Status::PartialFailure
+    }
+}
+
+/// Every file of the library but `skip`, usually the manifest itself.
+fn library_files(library_path: &Path, skip: Option<&Path>, summary: &mut Summary) -> Vec<PathBuf> {
+    let skip = skip.and_then(|skip| std::path::absolute(skip).ok());
+    let mut files = summary.phase("walk", |_| {
+        recurse_directory(&library_path.to_path_buf(), true, None, None)
+    });
+    files.retain(|file| skip.is_none() || std::path::absolute(file).ok() != skip);
+    files.sort();
+    summary.scanned = files.len();
+    files
+}
+
+/// Files listed on stdin for "-", named relative to the current folder in manifests.
+fn listed_files(summary: &mut Summary) -> Option<Vec<PathBuf>> {
+    match read_paths_from_stdin() {
+        Ok(mut files) => {
+            files.sort();
+            files.dedup();
+            summary.scanned = files.len();
+            Some(files)
+        }
+        Err(e) => {
+            error!("Could not read paths from stdin: {}", e);
+            None
+        }
+    }
+}
+
+/// Size and full hash of each file, in parallel. Files that cannot be read are logged and give
+/// `None`.
+fn hash_files(files: &[PathBuf], summary: &mut Summary) -> Vec<Option<(u64, u64)>> {
+    let hashed: Vec<Option<(u64, u64)>> = files
+        .par_iter()
+        .map(|file| {
+            if cancelled() {
+                return None;
+            }
+            let result = with_io_slot(|| {
+                let size = fs::metadata(file)?.len();
+                Ok::<_, std::io::Error>((size, full_hash(file)?))
+            });
+            result
+                .inspect_err(|e| {
+                    if !cancelled() {
+                        error!("Could not read {}: {}", file.display(), e)
+                    }
+                })
+                .ok()
+        })
+        .collect();
+    summary.errors += hashed.iter().filter(|hashed| hashed.is_none()).count();
+    hashed
+}
+
+fn relative_name(library_path: &Path, file: &Path) -> String {
+    file.strip_prefix(library_path)
+        .unwrap_or(file)
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn read_manifest(path: &Path) -> std::io::Result<Manifest> {
+    let content = fs::read_to_string(path)?;
+    let mut manifest = Manifest::new();
+    for line in content.lines().filter(|line| !line.starts_with('#')) {
+        let mut parts = line.splitn(3, ' ');
+        let (Some(hash), Some(size), Some(name)) = (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        if let (Ok(hash), Ok(size)) = (u64::from_str_radix(hash, 16), size.parse()) {
+            manifest.insert(name.to_string(), (size, hash));
+        }
+    }
+    Ok(manifest)
+}
+
+/// Last time each file matched a manifest, as "timestamp path" lines in the state folder.
+fn read_verified() -> HashMap<PathBuf, u64> {
+    let Some(content) = state_path(VERIFIED_FILE).and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return HashMap::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (timestamp, path) = line.split_once(' ')?;
+            Some((PathBuf::from(path), timestamp.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Failing to record verification times is only a warning, the verify itself is done.
+fn write_verified(verified: &HashMap<PathBuf, u64>) {
+    let Some(path) = state_path(VERIFIED_FILE) else {
+        return;
+    };
+    let mut entries: Vec<_> = verified.iter().collect();
+    entries.sort();
+    let mut content = String::new();
+    for (file, timestamp) in entries {
+        content.push_str(&format!("{} {}\n", timestamp, file.display()));
+    }
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| write_file(&path, |out| out.write_all(content.as_bytes())));
+    if let Err(e) = result {
+        warn!("Could not write {}: {}", path.display(), e);
+    }
+}
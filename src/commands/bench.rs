@@ -0,0 +1,107 @@
+use std::{path::PathBuf, time::Duration};
+
+use log::error;
+
+use crate::{
+    Status,
+    config::Profile,
+    fs::{quick_hash, recurse_directory},
+    library::{has_extension, read_tracks},
+    output::say,
+    summary::Summary,
+    track::DirtyTrack,
+};
+
+/// Time each stage of a scan on the real library, without using or updating the cache: listing
+/// the files, reading them for the quick hash, parsing the tags (in parallel, as scans do) and
+/// hashing them.
+pub fn run(library_path: Option<PathBuf>, profile: &Profile, summary: &mut Summary) -> Status {
+    let Some(library_path) = profile.library_path(library_path) else {
+        error!("No library path given and the profile does not define one");
+        return Status::Failure;
+    };
+    if !library_path.is_dir() {
+        error!("{} is not a folder", library_path.display());
+        return Status::Failure;
+    }
+
+    let extensions = profile.extensions();
+    let files = summary.phase("walk", |_| {
+        recurse_directory(
+            &library_path,
+            true,
+            Some(&|p: &PathBuf| has_extension(p, &extensions)),
+            None,
+        )
+    });
+    if files.is_empty() {
+        say!("No audio file found in {}", library_path.display());
+        return Status::NothingMatched;
+    }
+
+    let bytes: u64 = summary.phase("read", |summary| {
+        files
+            .iter()
+            .filter_map(|path| match quick_hash(path) {
+                Ok(_) => std::fs::metadata(path).ok().map(|m| m.len()),
+                Err(_) => {
+                    summary.errors += 1;
+                    None
+                }
+            })
+            .sum()
+    });
+    let tracks: Vec<DirtyTrack> = summary.phase("tags", |summary| {
+        let tracks = read_tracks(files.clone());
+        summary.errors += tracks.iter().filter(|t| t.read_error.is_some()).count();
+        tracks
+    });
+    summary.phase("hash", |_| {
+        tracks
+            .iter()
+            .map(DirtyTrack::tag_hash)
+            .fold(0, u64::wrapping_add)
+    });
+    summary.scanned = files.len();
+
+    let total: f64 = summary.phases.iter().map(|p| p.elapsed).sum();
+    say!(
+        "{} files, {:.1} MiB in {}",
+        files.len(),
+        bytes as f64 / (1024.0 * 1024.0),
+        library_path.display()
+    );
+    say!(
+        "{:<8} {:>9} {:>6} {:>12} {:>10}",
+        "stage",
+        "time",
+        "share",
+        "files/s",
+        "per file"
+    );
+    for phase in &summary.phases {
+        let per_file = Duration::from_secs_f64(phase.elapsed / files.len() as f64);
+        say!(
+            "{:<8} {:>8.2}s {:>5.0}% {:>12.0} {:>10.2?}",
+            phase.name,
+            phase.elapsed,
+            100.0 * phase.elapsed / total.max(f64::EPSILON),
+            files.len() as f64 / phase.elapsed.max(f64::EPSILON),
+            per_file
+        );
+    }
+
+    let io = summary
+        .phases
+        .iter()
+        .filter(|p| p.name == "walk" || p.name == "read")
+        .map(|p| p.elapsed)
+        .sum::<f64>();
+    if io > total / 2.0 {
+        say!("Most of the time goes to I/O, the storage is the bottleneck");
+    } else {
+        say!("Most of the time goes to tag parsing, which is CPU bound");
+    }
+    say!("Run again to compare with a warm filesystem cache");
+    Status::Ok
+}
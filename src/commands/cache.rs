@@ -0,0 +1,99 @@
+use log::error;
+
+use crate::{
+    Status,
+    cli::CacheAction,
+    commands::lock_cache,
+    fs::{Cache, format_datetime_u32},
+    library::Index,
+    output::{print_table, say},
+    summary::Summary,
+};
+
+pub fn run(action: CacheAction, summary: &mut Summary) -> Status {
+    match action {
+        CacheAction::Status => status(),
+        CacheAction::Clear => {
+            let _lock = lock_cache();
+            match Cache::clear().and_then(|()| Index::clear()) {
+                Ok(()) => {
+                    say!("Cache cleared");
+                    Status::Ok
+                }
+                Err(e) => {
+                    error!("Could not clear cache: {}", e);
+                    Status::Failure
+                }
+            }
+        }
+        CacheAction::Vacuum => {
+            let _lock = lock_cache();
+            let (mut cache, mut index) = summary.phase("read", |_| (Cache::new(), Index::load()));
+            let before = Cache::file_size().unwrap_or(0) + Index::file_size().unwrap_or(0);
+            let (dropped, unindexed) =
+                summary.phase("vacuum", |_| (cache.vacuum(), index.vacuum()));
+            let written = summary.phase("write", |_| {
+                cache.write_to_file().and_then(|()| index.write_to_file())
+            });
+            if let Err(e) = written {
+                error!("Could not write cache: {}", e);
+                return Status::Failure;
+            }
+            let after = Cache::file_size().unwrap_or(0) + Index::file_size().unwrap_or(0);
+            summary.scanned = cache.files.len() + dropped;
+            summary.modified = dropped;
+            summary.bytes_freed = before.saturating_sub(after);
+            say!(
+                "Dropped {} stale entries, {} kept, and {} of the index, {} kept ({} -> {} bytes)",
+                dropped,
+                cache.files.len(),
+                unindexed,
+                index.file_count(),
+                before,
+                after
+            );
+            Status::Ok
+        }
+    }
+}
+
+fn status() -> Status {
+    let Some(size) = Cache::file_size() else {
+        say!("No cache found");
+        return Status::NothingMatched;
+    };
+    let cache = Cache::new();
+    let stale = cache.files.keys().filter(|p| cache.has_changed(p)).count();
+
+    print_table(&[
+        vec!["Cache size:".to_string(), format!("{} bytes", size)],
+        vec![
+            "File entries:".to_string(),
+            format!("{} ({} stale)", cache.files.len(), stale),
+        ],
+        vec![
+            "Unsupported:".to_string(),
+            cache.unsupported.len().to_string(),
+        ],
+        vec![
+            "Library index:".to_string(),
+            match Index::file_size() {
+                Some(size) => format!("{} files, {} bytes", Index::load().file_count(), size),
+                None => "none".to_string(),
+            },
+        ],
+        vec![
+            "Last scan:".to_string(),
+            cache
+                .last_scan
+                .map_or("never".to_string(), format_datetime_u32),
+        ],
+        vec![
+            "Scan count:".to_string(),
+            cache
+                .scan_count
+                .map_or("unknown".to_string(), |c| c.to_string()),
+        ],
+    ]);
+    Status::Ok
+}
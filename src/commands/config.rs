@@ -0,0 +1,154 @@
+use std::{fmt::Display, fs};
+
+use crate::{
+    Status,
+    cli::ConfigAction,
+    config::{CommandOptions, Config, Profile, Setting, Settings, config_path},
+    fs::{DEFAULT_IO_JOBS, Deletion},
+    lyrics::SidecarNaming,
+    output::{Style, paint, print_table, say},
+};
+
+pub fn run(
+    action: ConfigAction,
+    config: &Config,
+    profile_name: Option<&str>,
+    profile: &Profile,
+    cli: &CommandOptions,
+    settings: &Settings,
+) -> Status {
+    match action {
+        ConfigAction::Show {
+            effective: false, ..
+        } => show_file(),
+        ConfigAction::Show {
+            effective: true,
+            command,
+        } => {
+            // Without --for, show the settings this run got. The profile of this run already
+            // holds them, so settings for another command start from the profile as configured
+            let for_command = command.map(|command| {
+                let configured = config.profile(profile_name);
+                let profile = configured.as_ref().unwrap_or(profile);
+                config.settings(Some(&command), cli, profile, profile_name.is_some())
+            });
+            show_effective(
+                config,
+                profile_name,
+                profile,
+                for_command.as_ref().unwrap_or(settings),
+            );
+            Status::Ok
+        }
+    }
+}
+
+fn show_file() -> Status {
+    let Some(path) = config_path() else {
+        say!("No config file: neither $XDG_CONFIG_HOME nor $HOME is set");
+        return Status::NothingMatched;
+    };
+    match fs::read_to_string(&path) {
+        Ok(content) => {
+            say!("# {}", path.display());
+            print!("{}", content);
+            Status::Ok
+        }
+        Err(e) => {
+            say!("No config file read from {}: {}", path.display(), e);
+            Status::NothingMatched
+        }
+    }
+}
+
+/// Print every setting as it applies to a run, with where the value comes from.
+fn show_effective(
+    config: &Config,
+    profile_name: Option<&str>,
+    profile: &Profile,
+    settings: &Settings,
+) {
+    let mut rows = Vec::new();
+    let mut line = |name: &str, value: &dyn Display, source: &dyn Display| {
+        rows.push(vec![
+            name.to_string(),
+            value.to_string(),
+            paint(format!("({})", source), Style::Dim),
+        ]);
+    };
+
+    match config_path() {
+        Some(path) if path.exists() => line("config", &path.display(), &"found"),
+        Some(path) => line("config", &path.display(), &"missing"),
+        None => line("config", &"-", &"no config folder"),
+    }
+    match (profile_name, &config.default_profile) {
+        (Some(name), _) => line("profile", &name, &"command line"),
+        (None, Some(name)) => line("profile", name, &"default_profile"),
+        (None, None) => line("profile", &"-", &"none"),
+    }
+    line(
+        "library",
+        &settings
+            .library
+            .value
+            .as_ref()
+            .map_or("-".to_string(), |p| p.display().to_string()),
+        &settings.library.source,
+    );
+    let extensions = Profile {
+        extensions: settings.extensions.value.clone(),
+        ..profile.clone()
+    };
+    line(
+        "extensions",
+        &extensions.extensions().join(", "),
+        &settings.extensions.source,
+    );
+    line(
+        "jobs",
+        &value_or(&settings.jobs, "number of CPUs"),
+        &settings.jobs.source,
+    );
+    line(
+        "io_jobs",
+        &value_or(&settings.io_jobs, &DEFAULT_IO_JOBS.to_string()),
+        &settings.io_jobs.source,
+    );
+    line(
+        "offline",
+        &settings.offline.value.unwrap_or(false),
+        &settings.offline.source,
+    );
+    line(
+        "deletion",
+        &settings.deletion.value.clone().unwrap_or(Deletion::Remove),
+        &settings.deletion.source,
+    );
+    line(
+        "tag.strip_keys",
+        &config.tag.strip_keys.join(", "),
+        &"config file",
+    );
+    line(
+        "lyrics.sidecar",
+        &match config.lyrics.sidecar {
+            SidecarNaming::Replace => "replace",
+            SidecarNaming::Append => "append",
+        },
+        &"config file",
+    );
+    line(
+        "hooks",
+        &config.hooks.values().map(Vec::len).sum::<usize>(),
+        &"config file",
+    );
+    line("webhooks", &config.webhooks.len(), &"config file");
+    print_table(&rows);
+}
+
+fn value_or(setting: &Setting<Option<usize>>, default: &str) -> String {
+    setting
+        .value
+        .map_or_else(|| default.to_string(), |value| value.to_string())
+}
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use log::error;
+
+use crate::{
+    Status,
+    fs::{civil_from_unix, unix_now},
+    journal::{self, Action, Entry},
+    output::{Style, paint, say},
+    summary::Summary,
+};
+
+/// Values longer than this are cut when listed.
+const MAX_VALUE_LENGTH: usize = 60;
+
+/// List what was done to `path`, or everywhere without one, oldest first.
+pub fn run(
+    path: Option<PathBuf>,
+    days: Option<u64>,
+    command: Option<String>,
+    json: bool,
+    summary: &mut Summary,
+) -> Status {
+    let entries = match journal::read() {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Could not read the journal: {}", e);
+            return Status::Failure;
+        }
+    };
+
+    let path = path.map(|path| std::path::absolute(&path).unwrap_or(path));
+    let since = days.map(|days| unix_now().saturating_sub(days * 24 * 60 * 60));
+    let entries: Vec<Entry> = entries
+        .into_iter()
+        .filter(|entry| path.as_deref().is_none_or(|path| entry.concerns(path)))
+        .filter(|entry| since.is_none_or(|since| entry.time >= since))
+        .filter(|entry| command.as_ref().is_none_or(|c| entry.command == *c))
+        .collect();
+
+    summary.matched = entries.len();
+    if entries.is_empty() {
+        say!("No matching change in the journal");
+        return Status::NothingMatched;
+    }
+
+    for entry in &entries {
+        if json {
+            match serde_json::to_string(entry) {
+                Ok(line) => println!("{}", line),
+                Err(e) => error!("Could not serialize journal entry: {}", e),
+            }
+            continue;
+        }
+
+        let (year, month, day, hour, minute) = civil_from_unix(entry.time);
+        let date = format!("{}-{:02}-{:02} {:02}:{:02}", year, month, day, hour, minute);
+        say!(
+            "{}  {:<8} {:<8} {}",
+            paint(date, Style::Dim),
+            entry.command,
+            entry.action.name(),
+            entry.path.display()
+        );
+        match entry.action {
+            Action::TagEdit => say!(
+                "    {}: {} -> {}",
+                entry.field.as_deref().unwrap_or_default(),
+                shorten(entry.before.as_deref()),
+                shorten(entry.after.as_deref())
+            ),
+            Action::Rename => say!("    -> {}", entry.after.as_deref().unwrap_or_default()),
+            Action::Delete if entry.after.is_some() => {
+                say!("    kept in {}", entry.after.as_deref().unwrap_or_default())
+            }
+            Action::Delete | Action::Write => {}
+        }
+    }
+    Status::Ok
+}
+
+/// First line of a value, cut to `MAX_VALUE_LENGTH` characters.
+fn shorten(value: Option<&str>) -> String {
+    let Some(value) = value else {
+        return "-".to_string();
+    };
+    let line = value.lines().next().unwrap_or_default();
+    if line.chars().count() > MAX_VALUE_LENGTH || line.len() < value.trim_end().len() {
+        format!(
+            "{}...",
+            line.chars().take(MAX_VALUE_LENGTH).collect::<String>()
+        )
+    } else {
+        line.to_string()
+    }
+}
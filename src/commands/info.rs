@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use log::error;
+
+use crate::{
+    Status,
+    output::{Style, paint, say},
+    summary::Summary,
+    track::DirtyTrack,
+};
+
+/// Print the metadata muman reads from each file.
+pub fn run(files: Vec<PathBuf>, summary: &mut Summary) -> Status {
+    let mut failed = 0;
+    for path in files {
+        let track = DirtyTrack::from(path.clone());
+        summary.scanned += 1;
+        if let Some(e) = &track.read_error {
+            error!("Could not read {}: {}", path.display(), e);
+            summary.errors += 1;
+            failed += 1;
+            continue;
+        }
+
+        say!("{}", paint(path.display(), Style::Bold));
+        for (name, value) in track.fields() {
+            say!("  {:<16}{}", format!("{}:", name), value);
+        }
+        if !track.extra.is_empty() {
+            say!("  Other fields:");
+            for (name, value) in &track.extra {
+                say!("    {:<24}{}", format!("{}:", name), value);
+            }
+        }
+    }
+
+    if failed == summary.scanned {
+        Status::Failure
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{error, warn};
+
+use crate::{
+    ALLOWED_EXTENSIONS, AUDIO_EXTENSIONS, Status,
+    commands::{ROOT_SAMPLE_SIZE, check_library_root},
+    config::config_path,
+    fs::{count_files, write_file},
+    output::{ask, choose, confirm, say},
+};
+
+/// Name of the profile written by the wizard, made the default one.
+const PROFILE: &str = "main";
+
+/// Where music usually lives, on desktops and on common NAS systems.
+const MUSIC_FOLDERS: &[&str] = &[
+    "/music",
+    "/srv/music",
+    "/mnt/music",
+    "/media/music",
+    "/volume1/music",
+    "/share/Music",
+    "/mnt/user/music",
+];
+
+pub fn run() -> Status {
+    let Some(path) = config_path() else {
+        error!("No config folder: neither $XDG_CONFIG_HOME nor $HOME is set");
+        return Status::Failure;
+    };
+    if path.exists() {
+        say!("A config file already exists at {}", path.display());
+        if !confirm("Replace it? The current one is kept next to it with a .bak extension") {
+            return Status::NothingMatched;
+        }
+    }
+
+    let Some(library) = pick_library() else {
+        error!("No music folder given, nothing written");
+        return Status::Failure;
+    };
+    if let Err(reason) = check_library_root(&library, &[]) {
+        warn!(
+            "{} does not look like a music library: {}. Commands that modify files will refuse it",
+            library.display(),
+            reason
+        );
+    }
+
+    let found = extension_counts(&library);
+    if !found.is_empty() {
+        say!(
+            "Found {}",
+            found
+                .iter()
+                .map(|(extension, count)| format!("{} {} files", count, extension))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    let default_extensions = if found.is_empty() {
+        ALLOWED_EXTENSIONS.join(", ")
+    } else {
+        found.keys().cloned().collect::<Vec<_>>().join(", ")
+    };
+    let extensions = split_list(&ask(
+        "File extensions to manage, separated by commas",
+        &default_extensions,
+    ))
+    .into_iter()
+    .map(|extension| extension.trim_start_matches('.').to_lowercase())
+    .collect::<Vec<_>>();
+
+    say!("Lyrics files next to the tracks can be named:");
+    say!("  1. Song.lrc, what most players look for");
+    say!("  2. Song.flac.lrc, for folders with the same song in several formats");
+    let sidecar = match choose("Lyrics file names", 2, Some(0)) {
+        Some(1) => "append",
+        _ => "replace",
+    };
+
+    say!("`muman tag strip` removes unwanted tags, always asking before it writes.");
+    let strip_keys = split_list(&ask(
+        "Tags it removes by default, e.g. comment,encoder, empty for none",
+        "",
+    ));
+    let offline = confirm("Work offline, without MusicBrainz lookups or webhooks?");
+
+    let content = config_file(&library, &extensions, sidecar, &strip_keys, offline);
+    match write_config(&path, &content) {
+        Ok(()) => {
+            say!("Wrote {}", path.display());
+            say!("Run `muman scan` to index the library, `muman config show` to review the config");
+            Status::Ok
+        }
+        Err(e) => {
+            error!("Could not write {}: {}", path.display(), e);
+            Status::Failure
+        }
+    }
+}
+
+/// Offer the music folders found on this machine, or ask for one. `None` when none is given.
+fn pick_library() -> Option<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let candidates: Vec<(PathBuf, usize)> = home
+        .iter()
+        .flat_map(|home| [home.join("Music"), home.join("music")])
+        .chain(MUSIC_FOLDERS.iter().map(PathBuf::from))
+        .filter(|folder| folder.is_dir())
+        .map(|folder| {
+            let audio = extension_counts(&folder).values().sum();
+            (folder, audio)
+        })
+        .filter(|(_, audio)| *audio > 0)
+        .collect();
+
+    if !candidates.is_empty() {
+        say!("Music folders found:");
+        for (i, (folder, audio)) in candidates.iter().enumerate() {
+            let more = if *audio >= ROOT_SAMPLE_SIZE { "+" } else { "" };
+            say!(
+                "  {}. {} ({}{} audio files)",
+                i + 1,
+                folder.display(),
+                audio,
+                more
+            );
+        }
+        say!("  {}. Another folder", candidates.len() + 1);
+        match choose("Music folder", candidates.len() + 1, Some(0)) {
+            Some(i) if i < candidates.len() => return Some(candidates[i].0.clone()),
+            Some(_) => {}
+            None => return None,
+        }
+    }
+
+    loop {
+        let answer = ask("Path of the music folder", "");
+        if answer.is_empty() {
+            return None;
+        }
+        let folder = PathBuf::from(answer);
+        if folder.is_dir() {
+            return Some(folder);
+        }
+        say!("{} is not a folder", folder.display());
+    }
+}
+
+/// Number of audio files per extension among the first files of `folder`.
+fn extension_counts(folder: &Path) -> BTreeMap<String, usize> {
+    let counts = RefCell::new(BTreeMap::new());
+    let tally = |file: &Path| {
+        let extension = file
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+            .filter(|e| AUDIO_EXTENSIONS.contains(&e.as_str()));
+        if let Some(extension) = extension {
+            *counts.borrow_mut().entry(extension).or_insert(0) += 1;
+        }
+        false
+    };
+    count_files(folder, &tally, ROOT_SAMPLE_SIZE);
+    counts.into_inner()
+}
+
+fn split_list(answer: &str) -> Vec<String> {
+    let mut items: Vec<String> = Vec::new();
+    for item in answer.split([',', ' ']).map(str::trim) {
+        if !item.is_empty() && !items.iter().any(|known| known == item) {
+            items.push(item.to_string());
+        }
+    }
+    items
+}
+
+fn config_file(
+    library: &Path,
+    extensions: &[String],
+    sidecar: &str,
+    strip_keys: &[String],
+    offline: bool,
+) -> String {
+    let mut profile = toml::Table::new();
+    let library = library
+        .canonicalize()
+        .unwrap_or_else(|_| library.to_path_buf());
+    profile.insert("library".into(), library.display().to_string().into());
+    profile.insert("extensions".into(), extensions.to_vec().into());
+    let mut profiles = toml::Table::new();
+    profiles.insert(PROFILE.into(), profile.into());
+
+    let mut config = toml::Table::new();
+    config.insert("default_profile".into(), PROFILE.into());
+    if offline {
+        config.insert("offline".into(), true.into());
+    }
+    config.insert("profiles".into(), profiles.into());
+    if !strip_keys.is_empty() {
+        let mut tag = toml::Table::new();
+        tag.insert("strip_keys".into(), strip_keys.to_vec().into());
+        config.insert("tag".into(), tag.into());
+    }
+    let mut lyrics = toml::Table::new();
+    lyrics.insert("sidecar".into(), sidecar.into());
+    config.insert("lyrics".into(), lyrics.into());
+
+    format!("# Written by `muman init`\n\n{}", config)
+}
+
+/// Write the config, keeping the previous one as `config.toml.bak`.
+fn write_config(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Some(folder) = path.parent() {
+        fs::create_dir_all(folder)?;
+    }
+    if path.exists() {
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::rename(path, backup)?;
+    }
+    write_file(path, |out| out.write_all(content.as_bytes()))
+}
@@ -0,0 +1,384 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use lofty::{file::TaggedFileExt, tag::ItemKey};
+use log::{error, warn};
+use rayon::prelude::*;
+
+use crate::{
+    Status,
+    artist::main_artist,
+    cli::{CoverageGroup, LyricsAction},
+    commands::{filter_tracks, load_library},
+    config::{Config, Profile},
+    fs::{delete_file, with_io_slot, write_file},
+    hooks::{Event, Hooks},
+    journal,
+    lyrics::{SidecarNaming, canonical_sidecar, diff, is_synced, normalize, sidecars},
+    output::{Style, choose, paint, print_table, say},
+    summary::Summary,
+    tag::edit_tag,
+    track::DirtyTrack,
+};
+
+pub fn run(
+    action: LyricsAction,
+    config: &Config,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Status {
+    match action {
+        LyricsAction::Reconcile {
+            library_path,
+            filter,
+            apply,
+            yes,
+        } => {
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            reconcile(
+                &library.tracks,
+                config.lyrics.sidecar,
+                hooks,
+                apply || yes,
+                yes,
+                summary,
+            )
+        }
+        LyricsAction::Coverage {
+            library_path,
+            filter,
+            by,
+        } => {
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            coverage(&library.tracks, by, config.lyrics.sidecar, summary)
+        }
+    }
+}
+
+/// One version of the lyrics of a track.
+struct Version {
+    /// "embedded" or the sidecar file name.
+    source: String,
+    text: String,
+}
+
+/// Find tracks whose embedded and sidecar lyrics disagree, or with both "Song.lrc" and
+/// "Song.flac.lrc", and keep a single version everywhere under the configured sidecar name.
+/// Synced lyrics are preferred over plain ones, other conflicts need a choice.
+fn reconcile(
+    tracks: &[DirtyTrack],
+    naming: SidecarNaming,
+    hooks: &Hooks,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let mut failed = 0;
+    for track in tracks {
+        let Some(path) = track.file_path.as_deref() else {
+            continue;
+        };
+        let sidecars = sidecars(path, naming);
+        let mut versions = Vec::new();
+        let mut unreadable = false;
+        if track.embedded_lyrics
+            && let Some(text) = embedded_lyrics(path)
+        {
+            versions.push(Version {
+                source: "embedded".to_string(),
+                text: normalize(&text),
+            });
+        }
+        for sidecar in &sidecars {
+            match fs::read_to_string(sidecar) {
+                Ok(text) => versions.push(Version {
+                    source: sidecar
+                        .file_name()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                        .into(),
+                    text: normalize(&text),
+                }),
+                Err(e) => {
+                    warn!("Could not read {}: {}", sidecar.display(), e);
+                    unreadable = true;
+                }
+            }
+        }
+        // Keeping a version removes the other sidecars, including those that could not be read
+        if unreadable {
+            warn!("Lyrics of {} left alone", path.display());
+            continue;
+        }
+
+        let mut distinct: Vec<&str> = versions.iter().map(|v| v.text.as_str()).collect();
+        distinct.sort();
+        distinct.dedup();
+        if versions.len() < 2 || (distinct.len() < 2 && sidecars.len() < 2) {
+            continue;
+        }
+        summary.matched += 1;
+
+        say!("{}", paint(path.display(), Style::Bold));
+        for (number, version) in versions.iter().enumerate() {
+            say!(
+                "  {}. {} ({}, {} lines)",
+                number + 1,
+                version.source,
+                if is_synced(&version.text) {
+                    "synced"
+                } else {
+                    "plain"
+                },
+                version.text.lines().count()
+            );
+        }
+        for version in &versions[1..] {
+            if version.text == versions[0].text {
+                continue;
+            }
+            say!("  --- {}\n  +++ {}", versions[0].source, version.source);
+            for (sign, line) in diff(&versions[0].text, &version.text) {
+                let line = format!("{}{}", sign, line);
+                match sign {
+                    '-' => say!("  {}", paint(line, Style::Error)),
+                    '+' => say!("  {}", paint(line, Style::Success)),
+                    _ => say!("  {}", line),
+                }
+            }
+        }
+
+        let synced: Vec<&str> = distinct.iter().copied().filter(|t| is_synced(t)).collect();
+        let preferred = match (distinct.as_slice(), synced.as_slice()) {
+            ([only], _) | (_, [only]) => versions.iter().position(|v| v.text == *only),
+            _ => None,
+        };
+
+        if !apply {
+            continue;
+        }
+        let choice = if yes {
+            if preferred.is_none() {
+                warn!("No preferred version for {}, skipped", path.display());
+            }
+            preferred
+        } else {
+            choose("Keep which version?", versions.len(), preferred)
+        };
+        let Some(choice) = choice else {
+            summary.skipped += 1;
+            continue;
+        };
+
+        if let Err(e) = keep_version(
+            path,
+            &versions[choice].text,
+            track.embedded_lyrics,
+            &sidecars,
+            naming,
+            hooks,
+        ) {
+            error!("Could not update lyrics of {}: {}", path.display(), e);
+            summary.errors += 1;
+            failed += 1;
+        } else {
+            summary.modified += 1;
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("No lyrics conflict");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Best lyrics a track has, embedded or in a sidecar file.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Coverage {
+    Synced,
+    Plain,
+    None,
+}
+
+/// Print the share of tracks with synced, plain or no lyrics in each group, the groups missing the
+/// most lyrics first, then the whole library.
+fn coverage(
+    tracks: &[DirtyTrack],
+    by: CoverageGroup,
+    naming: SidecarNaming,
+    summary: &mut Summary,
+) -> Status {
+    let coverages: Vec<Coverage> = tracks
+        .par_iter()
+        .map(|track| with_io_slot(|| track_coverage(track, naming)))
+        .collect();
+
+    // Tracks with synced, plain and no lyrics
+    let mut groups: BTreeMap<String, [usize; 3]> = BTreeMap::new();
+    let mut total = [0; 3];
+    for (track, coverage) in tracks.iter().zip(coverages) {
+        let group = match by {
+            CoverageGroup::Artist => track
+                .album_artist
+                .as_deref()
+                .or(track.artist.as_deref())
+                .map(|artist| main_artist(artist).to_string()),
+            CoverageGroup::Genre => track.genre.clone(),
+        };
+        let counts = groups
+            .entry(group.unwrap_or_else(|| "Unknown".to_string()))
+            .or_default();
+        counts[coverage as usize] += 1;
+        total[coverage as usize] += 1;
+        summary.matched += 1;
+    }
+    if summary.matched == 0 {
+        say!("No tracks");
+        return Status::NothingMatched;
+    }
+
+    let mut groups: Vec<(String, [usize; 3])> = groups.into_iter().collect();
+    groups.sort_by_key(|(_, counts)| std::cmp::Reverse(counts[Coverage::None as usize]));
+    let header = match by {
+        CoverageGroup::Artist => "Artist",
+        CoverageGroup::Genre => "Genre",
+    };
+    let mut rows = vec![
+        [header, "Tracks", "Synced", "Plain", "None"]
+            .map(|title| paint(title, Style::Bold))
+            .to_vec(),
+    ];
+    for (name, counts) in groups.iter().chain([&("Total".to_string(), total)]) {
+        let tracks: usize = counts.iter().sum();
+        let mut row = vec![name.clone(), tracks.to_string()];
+        row.extend(
+            counts
+                .iter()
+                .map(|count| format!("{:.0}%", *count as f64 * 100.0 / tracks as f64)),
+        );
+        rows.push(row);
+    }
+    print_table(&rows);
+    Status::Ok
+}
+
+fn track_coverage(track: &DirtyTrack, naming: SidecarNaming) -> Coverage {
+    let Some(path) = track.file_path.as_deref() else {
+        return Coverage::None;
+    };
+    let mut texts: Vec<String> = sidecars(path, naming)
+        .iter()
+        .filter_map(|sidecar| fs::read_to_string(sidecar).ok())
+        .collect();
+    if track.embedded_lyrics {
+        texts.extend(embedded_lyrics(path));
+    }
+    if texts.iter().any(|text| is_synced(text)) {
+        Coverage::Synced
+    } else if texts.iter().any(|text| !text.trim().is_empty()) {
+        Coverage::Plain
+    } else {
+        Coverage::None
+    }
+}
+
+fn embedded_lyrics(path: &Path) -> Option<String> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    tagged_file
+        .primary_tag()?
+        .get_string(&ItemKey::Lyrics)
+        .map(str::to_string)
+}
+
+/// Write `text` to the embedded lyrics if there were some, and to the canonical sidecar if there
+/// was any sidecar, removing the others.
+fn keep_version(
+    path: &Path,
+    text: &str,
+    embedded: bool,
+    sidecars: &[PathBuf],
+    naming: SidecarNaming,
+    hooks: &Hooks,
+) -> Result<(), String> {
+    if embedded {
+        edit_tag(path, |tag| {
+            if tag.get_string(&ItemKey::Lyrics) == Some(text) {
+                return false;
+            }
+            tag.insert_text(ItemKey::Lyrics, text.to_string());
+            true
+        })
+        .map_err(|e| e.to_string())?;
+    }
+
+    if sidecars.is_empty() {
+        return Ok(());
+    }
+    let canonical = canonical_sidecar(path, naming);
+    let content = format!("{}\n", text);
+    let previous = fs::read_to_string(&canonical).ok();
+    if previous.as_deref() != Some(content.as_str()) {
+        write_file(&canonical, |out| out.write_all(content.as_bytes()))
+            .map_err(|e| e.to_string())?;
+        journal::record_write(&canonical, previous, Some(content));
+    }
+    for sidecar in sidecars.iter().filter(|sidecar| **sidecar != canonical) {
+        let content = fs::read_to_string(sidecar).ok();
+        let kept = delete_file(sidecar).map_err(|e| e.to_string())?;
+        journal::record_delete(sidecar, content, kept.as_deref());
+        hooks.fire(Event::FileDeleted {
+            path: sidecar,
+            kept: kept.as_deref(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reconcile_skips_unreadable_sidecars() {
+        let folder = std::env::temp_dir().join(format!("muman-lyrics-{}", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("Song.flac");
+        fs::write(folder.join("Song.lrc"), [0xff, 0xfe, 0x00]).unwrap();
+        fs::write(folder.join("Song.flac.lrc"), [0xc3, 0x28]).unwrap();
+        let track = DirtyTrack {
+            file_path: Some(path.clone()),
+            ..Default::default()
+        };
+
+        let mut summary = Summary::new("lyrics");
+        let status = reconcile(
+            &[track],
+            SidecarNaming::Replace,
+            &Hooks::default(),
+            true,
+            true,
+            &mut summary,
+        );
+        assert_eq!(status, Status::NothingMatched);
+        assert!(folder.join("Song.lrc").is_file());
+        assert!(folder.join("Song.flac.lrc").is_file());
+        fs::remove_dir_all(&folder).unwrap();
+    }
+}
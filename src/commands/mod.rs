@@ -0,0 +1,340 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, error, warn};
+
+use crate::{
+    AUDIO_EXTENSIONS, Status, cancelled,
+    config::Profile,
+    filter::Filter,
+    fs::{
+        Cache, CacheLock, count_files, move_file, now_to_u32, read_paths_from_stdin,
+        recurse_directory, write_file,
+    },
+    hooks::{Event, Hooks},
+    journal,
+    library::{DirtyLibrary, Index},
+    lyrics::moved_sidecars,
+    output::{progress, say},
+    playlist::{normalize_path, retarget},
+    summary::Summary,
+    track::DirtyTrack,
+};
+
+/// Under this share of audio files, a folder does not look like a music library.
+const MIN_AUDIO_SHARE: f64 = 0.2;
+/// Playlists kept up to date when the tracks they list move.
+const PLAYLIST_EXTENSIONS: &[&str] = &["m3u", "m3u8", "cue"];
+/// Number of files looked at to compute that share.
+const ROOT_SAMPLE_SIZE: usize = 5000;
+/// Folders with fewer files are too small to judge.
+const ROOT_MIN_SAMPLE: usize = 50;
+
+pub mod albums;
+pub mod artists;
+pub mod backup;
+pub mod bench;
+pub mod cache;
+pub mod config;
+pub mod history;
+pub mod info;
+pub mod init;
+pub mod lyrics;
+pub mod playlist;
+pub mod report;
+pub mod scan;
+pub mod tag;
+pub mod undo;
+
+/// Library folder of commands that work on files rather than tracks: the given path or the
+/// profile's library.
+pub fn library_folder(profile: &Profile, library_path: Option<PathBuf>) -> Option<PathBuf> {
+    let Some(library_path) = profile.library_path(library_path) else {
+        error!("No library path given and the profile does not define one");
+        return None;
+    };
+    if !library_path.is_dir() {
+        error!("{} is not a folder", library_path.display());
+        return None;
+    }
+    Some(library_path)
+}
+
+/// Scan the library a command targets: the given path, the profile's library, or a list of files
+/// on stdin for "-". The cache is refreshed and hooks are fired for new and changed files.
+pub fn load_library(
+    library_path: Option<PathBuf>,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Result<DirtyLibrary, Status> {
+    let Some(library_path) = profile.library_path(library_path) else {
+        error!("No library path given and the profile does not define one");
+        return Err(Status::Failure);
+    };
+
+    let _lock = lock_cache();
+    let mut cache = Cache::new();
+    let mut index = summary.phase("index", |_| Index::load());
+    let library = if library_path.as_os_str() == "-" {
+        match read_paths_from_stdin() {
+            Ok(files) => DirtyLibrary::from_files(
+                library_path,
+                files,
+                &profile.extensions(),
+                &mut cache,
+                &mut index,
+                summary,
+            ),
+            Err(e) => {
+                error!("Could not read paths from stdin: {}", e);
+                return Err(Status::Failure);
+            }
+        }
+    } else {
+        DirtyLibrary::new(
+            library_path,
+            &profile.extensions(),
+            &mut cache,
+            &mut index,
+            summary,
+        )
+    };
+    if cancelled() {
+        error!("Cancelled, the cache is left as it was");
+        return Err(Status::Failure);
+    }
+
+    for track in &library.tracks {
+        if let (Some(path), Some(e)) = (&track.file_path, &track.read_error) {
+            if track.unsupported.is_some() {
+                warn!(
+                    "Could not read {}: {}. It is skipped until it changes",
+                    path.display(),
+                    e
+                );
+            } else {
+                warn!("Could not read {}: {}", path.display(), e);
+            }
+        }
+    }
+    for path in &library.added {
+        hooks.fire(Event::TrackAdded(path));
+    }
+    for path in &library.changed {
+        hooks.fire(Event::TrackChanged(path));
+    }
+    for (from, to) in &library.moved {
+        progress!("Moved: {} -> {}", from.display(), to.display());
+        hooks.fire(Event::TrackMoved { from, to });
+    }
+    if !library.moved.is_empty() {
+        cache.record_moves(&library.moved);
+        progress!(
+            "Run `muman scan --follow-moves` to carry their lyrics and playlist entries along"
+        );
+    }
+
+    cache.last_scan = Some(now_to_u32());
+    summary.phase("write", |_| {
+        if let Err(e) = cache.write_to_file() {
+            warn!("Could not write cache: {}", e);
+        }
+        if let Err(e) = index.write_to_file() {
+            warn!("Could not write the library index: {}", e);
+        }
+    });
+
+    Ok(library)
+}
+
+/// Carry what refers to the tracks moved under `library_path` along with them: their sidecar
+/// lyrics and CUE sheet follow them, and the playlists in the library point at their new paths.
+/// Without `apply`, only list what would change. Returns how many files changed or would.
+pub fn follow_moves(library_path: &Path, apply: bool) -> usize {
+    let _lock = lock_cache();
+    let mut cache = Cache::new();
+    let (moves, others): (Vec<_>, Vec<_>) = cache
+        .moved
+        .drain(..)
+        .partition(|(_, to)| library_path.as_os_str() == "-" || to.starts_with(library_path));
+    if moves.is_empty() {
+        return 0;
+    }
+
+    let mut changes = 0;
+    // Sheets that follow their track still list it relative to where they were
+    let mut moved_sheets = Vec::new();
+    for (from, to) in &moves {
+        for (old, new) in moved_sidecars(from, to) {
+            changes += 1;
+            if !apply {
+                say!("Would move {} -> {}", old.display(), new.display());
+            } else if let Err(e) = move_file(&old, &new) {
+                warn!(
+                    "Could not move {} to {}: {}",
+                    old.display(),
+                    new.display(),
+                    e
+                );
+                continue;
+            } else {
+                progress!("Moved {} -> {}", old.display(), new.display());
+                journal::record_rename(&old, &new);
+            }
+            if let Some(folder) = old.parent().filter(|_| is_playlist(&new)) {
+                moved_sheets.push((folder.to_path_buf(), old, new));
+            }
+        }
+    }
+
+    let moved: HashMap<PathBuf, PathBuf> = moves
+        .iter()
+        .map(|(from, to)| (normalize_path(from), normalize_path(to)))
+        .collect();
+    for (folder, old, new) in &moved_sheets {
+        let source = if apply { new } else { old };
+        changes += usize::from(retarget_playlist(source, new, folder, &moved, apply));
+    }
+    if library_path.is_dir() {
+        let is_playlist = |path: &PathBuf| is_playlist(path);
+        for playlist in
+            recurse_directory(&library_path.to_path_buf(), true, Some(&is_playlist), None)
+        {
+            if !moved_sheets
+                .iter()
+                .any(|(_, old, new)| *old == playlist || *new == playlist)
+            {
+                let folder = playlist.parent().unwrap_or(Path::new("."));
+                changes += usize::from(retarget_playlist(
+                    &playlist, &playlist, folder, &moved, apply,
+                ));
+            }
+        }
+    }
+
+    if apply {
+        cache.moved = others;
+        if let Err(e) = cache.write_to_file() {
+            warn!("Could not write cache: {}", e);
+        }
+    }
+    changes
+}
+
+fn is_playlist(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| PLAYLIST_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+}
+
+/// Rewrite the entries of the playlist read from `source`, previously in `folder`, that point at
+/// moved tracks, into `path`. Without `apply`, only say it would. Returns whether entries changed.
+fn retarget_playlist(
+    source: &Path,
+    path: &Path,
+    folder: &Path,
+    moved: &HashMap<PathBuf, PathBuf>,
+    apply: bool,
+) -> bool {
+    let text = match fs::read_to_string(source) {
+        Ok(text) => text,
+        Err(e) => {
+            debug!("Could not read playlist {}: {}", source.display(), e);
+            return false;
+        }
+    };
+    let cue = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cue"));
+    let here = path.parent().unwrap_or(Path::new("."));
+    let Some(updated) = retarget(&text, cue, folder, here, moved) else {
+        return false;
+    };
+    if !apply {
+        say!("Would update {} for moved tracks", path.display());
+        return true;
+    }
+    match write_file(path, |out| out.write_all(updated.as_bytes())) {
+        Ok(()) => {
+            progress!("Updated {} for moved tracks", path.display());
+            journal::record_write(path, Some(text), Some(updated));
+        }
+        Err(e) => warn!("Could not update {}: {}", path.display(), e),
+    }
+    true
+}
+
+/// Keep the tracks matching the `--where` expression, if any.
+pub fn filter_tracks(tracks: &mut Vec<DirtyTrack>, filter: Option<&Filter>) {
+    if let Some(filter) = filter {
+        tracks.retain(|track| filter.matches(track));
+    }
+}
+
+/// Keep the albums with at least one track matching the `--where` expression, if any, with all
+/// their tracks. For commands that need whole albums, like renumbering.
+pub fn filter_albums(tracks: &mut Vec<DirtyTrack>, filter: Option<&Filter>) {
+    let Some(filter) = filter else {
+        return;
+    };
+    let album_of = |track: &DirtyTrack| {
+        Some((
+            track.file_path.as_deref()?.parent()?.to_path_buf(),
+            track.album.clone()?,
+        ))
+    };
+    let albums: HashSet<_> = tracks
+        .iter()
+        .filter(|track| filter.matches(track))
+        .filter_map(album_of)
+        .collect();
+    tracks.retain(|track| album_of(track).is_some_and(|album| albums.contains(&album)));
+}
+
+/// Lock the cache before updating it. Where locking is not supported the update goes on unlocked.
+pub fn lock_cache() -> Option<CacheLock> {
+    Cache::lock()
+        .inspect_err(|e| warn!("Could not lock the cache, updating it anyway: {}", e))
+        .ok()
+}
+
+/// Check that a library a command is about to modify looks like one: not "/", the home folder or
+/// one of its parents, and made of a fair share of audio files. A typo in a library path should
+/// not point destructive commands at everything.
+pub fn check_library_root(path: &Path, extensions: &[String]) -> Result<(), String> {
+    if path.as_os_str() == "-" {
+        return Ok(());
+    }
+    let path = path
+        .canonicalize()
+        .map_err(|e| format!("{} cannot be resolved: {}", path.display(), e))?;
+    if path.parent().is_none() {
+        return Err("it is the filesystem root".to_string());
+    }
+    if let Some(home) =
+        std::env::var_os("HOME").and_then(|home| Path::new(&home).canonicalize().ok())
+        && home.starts_with(&path)
+    {
+        return Err("it is the home folder or one of its parents".to_string());
+    }
+
+    let is_audio = |file: &Path| {
+        file.extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_lowercase)
+            .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.as_str()) || extensions.contains(&e))
+    };
+    let (total, audio) = count_files(&path, &is_audio, ROOT_SAMPLE_SIZE);
+    if total >= ROOT_MIN_SAMPLE && (audio as f64) < total as f64 * MIN_AUDIO_SHARE {
+        return Err(format!(
+            "only {} of the first {} files are audio files",
+            audio, total
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,721 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::{self, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use log::{error, warn};
+
+use crate::{
+    Status,
+    album::album_cover,
+    artist::main_artist,
+    cli::{OnThisDay, PlaylistAction, PlaylistFormat, PlaylistOutput},
+    commands::{filter_tracks, load_library},
+    config::{Config, ExportProfile, Profile},
+    fs::{Cache, civil_from_unix, state_path, unix_now, write_file},
+    hooks::Hooks,
+    journal,
+    library::group_by_album,
+    output::{progress, say},
+    playlist::{PlaylistStyle, write_cue, write_m3u},
+    summary::Summary,
+    track::DirtyTrack,
+};
+
+/// When rotation playlists last picked each track.
+const ROTATED_FILE: &str = "rotated.txt";
+/// Difference of ReplayGain track gains, in dB, from which tracks are as far apart as can be.
+const MAX_GAIN_GAP: f64 = 6.0;
+/// Years between releases from which tracks are as far apart as can be.
+const MAX_YEAR_GAP: f64 = 15.0;
+/// Relative tempo difference from which tracks are as far apart as can be.
+const MAX_TEMPO_GAP: f64 = 0.1;
+
+pub fn run(
+    action: PlaylistAction,
+    config: &Config,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Status {
+    match action {
+        PlaylistAction::OnThisDay {
+            library_path,
+            filter,
+            mode,
+            years_ago,
+            output,
+        } => {
+            let Some(export) = resolve_export(config, output.export.as_deref()) else {
+                return Status::Failure;
+            };
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            let tracks = match mode {
+                OnThisDay::Released => released_this_month(&library.tracks),
+                OnThisDay::Added => added_years_ago(&library.tracks, &Cache::new(), years_ago),
+            };
+            write_playlist(&tracks, &library.path, &output, export, summary)
+        }
+        PlaylistAction::Sample {
+            library_path,
+            filter,
+            max,
+            per_artist,
+            genre,
+            tags,
+            duration,
+            seed,
+            output,
+        } => {
+            let Some(export) = resolve_export(config, output.export.as_deref()) else {
+                return Status::Failure;
+            };
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            let constraints = SampleConstraints {
+                max,
+                per_artist,
+                genre,
+                tags,
+                duration: duration.map(|minutes| minutes * 60),
+            };
+            let mut rng = seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+            let tracks = sample(&library.tracks, &constraints, &mut rng);
+            write_playlist(&tracks, &library.path, &output, export, summary)
+        }
+        PlaylistAction::Rotation {
+            library_path,
+            filter,
+            count,
+            recent_share,
+            recent_days,
+            half_life,
+            seed,
+            output,
+        } => {
+            let Some(export) = resolve_export(config, output.export.as_deref()) else {
+                return Status::Failure;
+            };
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            let settings = RotationSettings {
+                count,
+                recent_share,
+                recent_days,
+                half_life,
+            };
+            let mut rotated = read_rotated();
+            let mut rng = seed.map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+            let tracks = rotation(
+                &library.tracks,
+                &Cache::new(),
+                &rotated,
+                &settings,
+                &mut rng,
+            );
+            let status = write_playlist(&tracks, &library.path, &output, export, summary);
+            if status == Status::Ok {
+                let now = unix_now();
+                rotated.extend(
+                    tracks
+                        .iter()
+                        .filter_map(|track| Some((track.file_path.clone()?, now))),
+                );
+                write_rotated(&rotated);
+            }
+            status
+        }
+        PlaylistAction::Similar {
+            file,
+            library_path,
+            filter,
+            count,
+            output,
+        } => {
+            let Some(export) = resolve_export(config, output.export.as_deref()) else {
+                return Status::Failure;
+            };
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            let seed = seed_track(&file, &library.path, &library.tracks);
+            if let Some(e) = &seed.read_error {
+                error!("Could not read {}: {}", file.display(), e);
+                return Status::Failure;
+            }
+            if distance(&seed, &seed).is_none() {
+                error!(
+                    "{} has none of the tags tracks are compared on: BPM, ReplayGain, genre, year \
+                     or artist",
+                    file.display()
+                );
+                return Status::Failure;
+            }
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            let mut tracks = vec![&seed];
+            tracks.extend(similar(&seed, &library.tracks, count));
+            write_playlist(&tracks, &library.path, &output, export, summary)
+        }
+        PlaylistAction::Cue {
+            library_path,
+            single_file,
+            force,
+            export,
+        } => {
+            let Some(export) = resolve_export(config, export.as_deref()) else {
+                return Status::Failure;
+            };
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            album_cue_sheets(
+                &library.tracks,
+                single_file.as_deref(),
+                force,
+                ExportProfile {
+                    relative: true,
+                    root: None,
+                    ..export
+                },
+                summary,
+            )
+        }
+    }
+}
+
+struct SampleConstraints {
+    max: usize,
+    per_artist: Option<usize>,
+    genre: Option<String>,
+    /// Values required in extra fields.
+    tags: Vec<(String, String)>,
+    /// Duration budget in seconds.
+    duration: Option<u32>,
+}
+
+/// Pick random tracks one by one, each time taking the first of the shuffled pool that is not by
+/// the previous artist and still fits the per-artist and duration limits.
+fn sample<'a>(
+    tracks: &'a [DirtyTrack],
+    constraints: &SampleConstraints,
+    rng: &mut fastrand::Rng,
+) -> Vec<&'a DirtyTrack> {
+    let artist_of = |track: &DirtyTrack| {
+        track
+            .artist
+            .as_deref()
+            .map(|artist| main_artist(artist).to_lowercase())
+    };
+
+    let mut pool: Vec<&DirtyTrack> = tracks
+        .iter()
+        .filter(|track| {
+            constraints.genre.as_ref().is_none_or(|genre| {
+                track
+                    .genre
+                    .as_ref()
+                    .is_some_and(|g| g.eq_ignore_ascii_case(genre))
+            })
+        })
+        .filter(|track| {
+            constraints.tags.iter().all(|(field, value)| {
+                track
+                    .extra_field(field)
+                    .is_some_and(|v| v.split("; ").any(|v| v.eq_ignore_ascii_case(value)))
+            })
+        })
+        .collect();
+    rng.shuffle(&mut pool);
+
+    let mut picked = Vec::new();
+    let mut per_artist: HashMap<Option<String>, usize> = HashMap::new();
+    let mut remaining = constraints.duration;
+    let mut previous = None;
+    while picked.len() < constraints.max {
+        let next = pool.iter().position(|track| {
+            let artist = artist_of(track);
+            (artist.is_none() || artist != previous)
+                && constraints
+                    .per_artist
+                    .is_none_or(|max| per_artist.get(&artist).copied().unwrap_or(0) < max)
+                && remaining.is_none_or(|left| track.duration.unwrap_or(0) <= left)
+        });
+        let Some(index) = next else {
+            break;
+        };
+
+        let track = pool.swap_remove(index);
+        let artist = artist_of(track);
+        *per_artist.entry(artist.clone()).or_default() += 1;
+        if let Some(left) = &mut remaining {
+            *left -= track.duration.unwrap_or(0);
+        }
+        previous = artist;
+        picked.push(track);
+    }
+    picked
+}
+
+struct RotationSettings {
+    count: usize,
+    /// Share of the playlist taken from recent additions.
+    recent_share: f64,
+    /// Age in days under which a track is a recent addition.
+    recent_days: u64,
+    /// Days after which a rotated track is half as likely to be picked again.
+    half_life: u64,
+}
+
+/// Pick recent additions and back catalog tracks in the configured proportions, each side
+/// filling in for the other when it runs short, then shuffle them together. Tracks are weighted
+/// by how long ago a rotation picked them, never picked ones the most.
+fn rotation<'a>(
+    tracks: &'a [DirtyTrack],
+    cache: &Cache,
+    rotated: &HashMap<PathBuf, u64>,
+    settings: &RotationSettings,
+    rng: &mut fastrand::Rng,
+) -> Vec<&'a DirtyTrack> {
+    let now = unix_now();
+    let recent_since = now.saturating_sub(settings.recent_days * 24 * 60 * 60);
+    let (recent, back_catalog): (Vec<&DirtyTrack>, Vec<&DirtyTrack>) =
+        tracks.iter().partition(|track| {
+            track
+                .file_path
+                .as_ref()
+                .and_then(|path| cache.files.get(path))
+                .is_some_and(|entry| entry.first_seen >= recent_since)
+        });
+
+    let weight = |track: &DirtyTrack| {
+        let picked = track.file_path.as_ref().and_then(|path| rotated.get(path));
+        let Some(picked) = picked else {
+            return 1.0;
+        };
+        let days = now.saturating_sub(*picked) as f64 / (24.0 * 60.0 * 60.0);
+        1.0 - 0.5_f64.powf(days / settings.half_life.max(1) as f64)
+    };
+    // Weighted sampling without replacement: the largest of random^(1/weight) win
+    let mut pick = |pool: Vec<&'a DirtyTrack>| {
+        let mut keyed: Vec<(f64, &DirtyTrack)> = pool
+            .into_iter()
+            .map(|track| {
+                let weight = weight(track);
+                let key = if weight > 0.0 {
+                    rng.f64().powf(1.0 / weight)
+                } else {
+                    0.0
+                };
+                (key, track)
+            })
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        keyed.into_iter().map(|(_, track)| track)
+    };
+
+    let wanted_recent = (settings.count as f64 * settings.recent_share).round() as usize;
+    let recent_count = wanted_recent
+        .max(settings.count.saturating_sub(back_catalog.len()))
+        .min(recent.len());
+    let mut picked: Vec<&DirtyTrack> = pick(recent).take(recent_count).collect();
+    picked.extend(pick(back_catalog).take(settings.count - picked.len()));
+    rng.shuffle(&mut picked);
+    picked
+}
+
+/// When rotations last picked each track, as "timestamp path" lines in the state folder.
+fn read_rotated() -> HashMap<PathBuf, u64> {
+    let Some(content) = state_path(ROTATED_FILE).and_then(|path| fs::read_to_string(path).ok())
+    else {
+        return HashMap::new();
+    };
+    content
+        .lines()
+        .filter_map(|line| {
+            let (timestamp, path) = line.split_once(' ')?;
+            Some((PathBuf::from(path), timestamp.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Failing to record the picks is only a warning, the playlist itself is written.
+fn write_rotated(rotated: &HashMap<PathBuf, u64>) {
+    let Some(path) = state_path(ROTATED_FILE) else {
+        return;
+    };
+    let mut entries: Vec<_> = rotated.iter().collect();
+    entries.sort();
+    let mut content = String::new();
+    for (file, timestamp) in entries {
+        content.push_str(&format!("{} {}\n", timestamp, file.display()));
+    }
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| write_file(&path, |out| out.write_all(content.as_bytes())));
+    if let Err(e) = result {
+        warn!("Could not write {}: {}", path.display(), e);
+    }
+}
+
+/// The library's track for `file` when it is in the library, so the cached tags are used, or the
+/// file read on its own.
+fn seed_track(file: &Path, library: &Path, tracks: &[DirtyTrack]) -> DirtyTrack {
+    let in_library = file
+        .canonicalize()
+        .ok()
+        .zip(library.canonicalize().ok())
+        .and_then(|(file, root)| Some(library.join(file.strip_prefix(root).ok()?)));
+    in_library
+        .and_then(|path| {
+            tracks
+                .iter()
+                .find(|track| track.file_path.as_ref() == Some(&path))
+                .cloned()
+        })
+        .unwrap_or_else(|| DirtyTrack::from(file.to_path_buf()))
+}
+
+/// The `count` tracks closest to `seed`, closest first. Other copies of the seed, with the same
+/// title and artist, are left out.
+fn similar<'a>(seed: &DirtyTrack, tracks: &'a [DirtyTrack], count: usize) -> Vec<&'a DirtyTrack> {
+    let same_recording = |track: &DirtyTrack| {
+        track.file_path == seed.file_path
+            || (track.title.as_deref().map(str::to_lowercase)
+                == seed.title.as_deref().map(str::to_lowercase)
+                && artist_key(track) == artist_key(seed))
+    };
+    let mut ranked: Vec<(f64, &DirtyTrack)> = tracks
+        .iter()
+        .filter(|track| !same_recording(track))
+        .filter_map(|track| Some((distance(seed, track)?, track)))
+        .collect();
+    ranked.sort_by(|(a, track_a), (b, track_b)| {
+        a.total_cmp(b)
+            .then_with(|| track_a.file_path.cmp(&track_b.file_path))
+    });
+    ranked
+        .into_iter()
+        .take(count)
+        .map(|(_, track)| track)
+        .collect()
+}
+
+/// How far `track` sounds from `seed`, from 0 for the same tempo, loudness, genre, year and main
+/// artist to 1. Only the tags `seed` has are compared, a track missing one counts as halfway on
+/// it. `None` when `seed` has none of them.
+fn distance(seed: &DirtyTrack, track: &DirtyTrack) -> Option<f64> {
+    let differs = |same: bool| if same { 0.0 } else { 1.0 };
+    // Weight of each tag, and the distance on it when both tracks have it
+    let mut parts: Vec<(f64, Option<f64>)> = Vec::new();
+    if let Some(bpm) = seed.bpm() {
+        parts.push((3.0, track.bpm().map(|other| tempo_distance(bpm, other))));
+    }
+    if let Some(gain) = seed.track_gain() {
+        let loudness = |other: f64| ((gain - other).abs() / MAX_GAIN_GAP).min(1.0);
+        parts.push((2.0, track.track_gain().map(loudness)));
+    }
+    if let Some(seed_genres) = genres(seed) {
+        let shared = |other: Vec<String>| differs(seed_genres.iter().any(|g| other.contains(g)));
+        parts.push((2.0, genres(track).map(shared)));
+    }
+    if let Some(year) = seed.year {
+        let era = |other: u32| (year.abs_diff(other) as f64 / MAX_YEAR_GAP).min(1.0);
+        parts.push((1.0, track.year.map(era)));
+    }
+    if let Some(artist) = artist_key(seed) {
+        parts.push((1.0, artist_key(track).map(|other| differs(other == artist))));
+    }
+
+    let weights: f64 = parts.iter().map(|(weight, _)| weight).sum();
+    (weights > 0.0).then(|| {
+        parts
+            .iter()
+            .map(|(weight, distance)| weight * distance.unwrap_or(0.5))
+            .sum::<f64>()
+            / weights
+    })
+}
+
+/// Relative tempo difference, where half and double tempo are as close as the same tempo since
+/// beat detectors often pick either.
+fn tempo_distance(bpm: f64, other: f64) -> f64 {
+    [1.0, 2.0, 0.5]
+        .iter()
+        .map(|ratio| (bpm * ratio - other).abs() / other)
+        .fold(f64::INFINITY, f64::min)
+        .min(MAX_TEMPO_GAP)
+        / MAX_TEMPO_GAP
+}
+
+fn genres(track: &DirtyTrack) -> Option<Vec<String>> {
+    let genre = track.genre.as_deref()?;
+    Some(genre.split("; ").map(|g| g.trim().to_lowercase()).collect())
+}
+
+fn artist_key(track: &DirtyTrack) -> Option<String> {
+    track
+        .artist
+        .as_deref()
+        .map(|artist| main_artist(artist).to_lowercase())
+}
+
+/// Tracks released in the current month of previous years, oldest first.
+fn released_this_month(tracks: &[DirtyTrack]) -> Vec<&DirtyTrack> {
+    let (year, month, ..) = civil_from_unix(unix_now());
+    let mut found: Vec<&DirtyTrack> = tracks
+        .iter()
+        .filter(|t| t.release_month() == Some(month) && t.year.is_some_and(|y| y < year))
+        .collect();
+    found.sort_by(|a, b| a.date.cmp(&b.date));
+    found
+}
+
+/// Tracks first seen by a scan during the current month, `years_ago` years back.
+fn added_years_ago<'a>(
+    tracks: &'a [DirtyTrack],
+    cache: &Cache,
+    years_ago: u32,
+) -> Vec<&'a DirtyTrack> {
+    let (year, month, ..) = civil_from_unix(unix_now());
+    let target = (year.saturating_sub(years_ago), month);
+
+    let mut found: Vec<(u64, &DirtyTrack)> = tracks
+        .iter()
+        .filter_map(|track| {
+            let entry = cache.files.get(track.file_path.as_ref()?)?;
+            let (year, month, ..) = civil_from_unix(entry.first_seen);
+            ((year, month) == target).then_some((entry.first_seen, track))
+        })
+        .collect();
+    found.sort_by_key(|(first_seen, _)| *first_seen);
+    found.into_iter().map(|(_, track)| track).collect()
+}
+
+/// The named export profile, or the default one without a name.
+fn resolve_export(config: &Config, name: Option<&str>) -> Option<ExportProfile> {
+    let Some(name) = name else {
+        return Some(ExportProfile::default());
+    };
+    let export = config.export(name);
+    if export.is_none() {
+        error!("Unknown export profile: {}", name);
+    }
+    export
+}
+
+/// Write the playlist to `output`, or stdout without one, then the covers it asks for.
+fn write_playlist(
+    tracks: &[&DirtyTrack],
+    library: &Path,
+    output: &PlaylistOutput,
+    export: ExportProfile,
+    summary: &mut Summary,
+) -> Status {
+    summary.matched = tracks.len();
+    if tracks.is_empty() {
+        error!("No track matched, no playlist written");
+        return Status::NothingMatched;
+    }
+    let style = PlaylistStyle {
+        export,
+        folder: Some(
+            output
+                .output
+                .as_deref()
+                .and_then(Path::parent)
+                .unwrap_or(Path::new("."))
+                .to_path_buf(),
+        ),
+        library: Some(library.to_path_buf()),
+    };
+
+    let write = |out: &mut dyn Write| match output.format {
+        PlaylistFormat::M3u => write_m3u(out, tracks, &style),
+        PlaylistFormat::Cue => {
+            let title = output
+                .output
+                .as_deref()
+                .and_then(|path| path.file_stem()?.to_str());
+            write_cue(out, title, tracks, output.single_file.as_deref(), &style)
+        }
+    };
+    let result = match &output.output {
+        Some(path) => write_file(path, |out| write(out)),
+        None => write(&mut BufWriter::new(io::stdout().lock())),
+    };
+    if let Err(e) = result {
+        error!("Could not write playlist: {}", e);
+        return Status::Failure;
+    }
+
+    if output.covers.is_none() && !output.playlist_cover {
+        return Status::Ok;
+    }
+    // Albums of the playlist by folder, the one with the most tracks first
+    let mut albums: BTreeMap<&Path, Vec<&DirtyTrack>> = BTreeMap::new();
+    for track in tracks {
+        if let Some(folder) = track.file_path.as_deref().and_then(Path::parent) {
+            albums.entry(folder).or_default().push(track);
+        }
+    }
+    let mut albums: Vec<(&Path, Vec<&DirtyTrack>)> = albums.into_iter().collect();
+    albums.sort_by_key(|(_, tracks)| std::cmp::Reverse(tracks.len()));
+
+    if let Some(covers) = &output.covers {
+        copy_covers(&albums, library, covers, summary);
+    }
+    if output.playlist_cover
+        && let Some(playlist) = &output.output
+    {
+        let cover = albums
+            .iter()
+            .find_map(|(folder, tracks)| album_cover(folder, tracks));
+        match cover {
+            Some((data, extension)) => {
+                let path = playlist.with_extension(extension);
+                match write_file(&path, |out| out.write_all(&data)) {
+                    Ok(()) => say!("Wrote {}", path.display()),
+                    Err(e) => {
+                        error!("Could not write {}: {}", path.display(), e);
+                        summary.errors += 1;
+                    }
+                }
+            }
+            None => say!("No album of the playlist has a cover"),
+        }
+    }
+    if summary.errors > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Copy the cover of each album to "cover.<extension>" in `covers`, under the path of the album
+/// folder inside the library, so that the tree can be copied next to the tracks on a device. The
+/// count goes to stderr as the playlist may be on stdout.
+fn copy_covers(
+    albums: &[(&Path, Vec<&DirtyTrack>)],
+    library: &Path,
+    covers: &Path,
+    summary: &mut Summary,
+) {
+    let mut copied = 0;
+    for (folder, tracks) in albums {
+        let Some((data, extension)) = album_cover(folder, tracks) else {
+            continue;
+        };
+        // Only the normal components, an absolute folder outside the library must not escape
+        let relative: PathBuf = folder
+            .strip_prefix(library)
+            .unwrap_or(folder)
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect();
+        let folder = covers.join(relative);
+        let path = folder.join(format!("cover.{}", extension));
+        match fs::create_dir_all(&folder)
+            .and_then(|()| write_file(&path, |out| out.write_all(&data)))
+        {
+            Ok(()) => copied += 1,
+            Err(e) => {
+                error!("Could not write {}: {}", path.display(), e);
+                summary.errors += 1;
+            }
+        }
+    }
+    progress!(
+        "Copied {} of {} album covers to {}",
+        copied,
+        albums.len(),
+        covers.display()
+    );
+}
+
+/// Write "<album>.cue" in the folder of every album, in disc and track order. Existing sheets are
+/// kept unless `force` is set.
+fn album_cue_sheets(
+    tracks: &[DirtyTrack],
+    single_file: Option<&Path>,
+    force: bool,
+    export: ExportProfile,
+    summary: &mut Summary,
+) -> Status {
+    let mut failed = 0;
+    for ((folder, album), mut tracks) in group_by_album(tracks) {
+        tracks.sort_by_key(|t| {
+            (
+                t.disc_number.unwrap_or(1),
+                t.track_number.unwrap_or(u32::MAX),
+                t.file_path.clone(),
+            )
+        });
+        summary.matched += 1;
+
+        let name = if album.is_empty() {
+            folder.file_name().unwrap_or_default().to_string_lossy()
+        } else {
+            album.into()
+        };
+        let cue_path = folder.join(format!("{}.cue", name.replace(['/', '\\'], "_")));
+        if cue_path.exists() && !force {
+            say!("{} already exists, skipped", cue_path.display());
+            summary.skipped += 1;
+            continue;
+        }
+
+        let single_file = single_file.map(|file| folder.join(file));
+        let style = PlaylistStyle {
+            export: export.clone(),
+            folder: Some(folder.to_path_buf()),
+            library: None,
+        };
+        let mut sheet = Vec::new();
+        let previous = fs::read_to_string(&cue_path).ok();
+        let result = write_cue(
+            &mut sheet,
+            Some(&name),
+            &tracks,
+            single_file.as_deref(),
+            &style,
+        )
+        .and_then(|()| write_file(&cue_path, |out| out.write_all(&sheet)));
+        match result {
+            Ok(()) => {
+                let content = String::from_utf8_lossy(&sheet).into_owned();
+                journal::record_write(&cue_path, previous, Some(content));
+                say!("Wrote {}", cue_path.display());
+                summary.modified += 1;
+            }
+            Err(e) => {
+                error!("Could not write {}: {}", cue_path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            }
+        }
+    }
+
+    if summary.matched == 0 {
+        say!("No album found");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
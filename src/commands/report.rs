@@ -0,0 +1,210 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Component, Path},
+};
+
+use log::error;
+
+use crate::{
+    Status,
+    album::{album_artist, album_country, album_cover},
+    cli::ReportAction,
+    commands::load_library,
+    config::{Config, Profile},
+    fs::write_file,
+    hooks::Hooks,
+    image::{ImageInfo, image_info},
+    library::{DirtyLibrary, group_by_album},
+    lyrics::{self, SidecarNaming},
+    output::{Style, paint, say},
+    report::{AlbumCard, Quality, finding_id, write_html},
+    summary::Summary,
+    track::DirtyTrack,
+};
+
+/// Folder of the report holding the album covers.
+const COVERS_DIR: &str = "covers";
+
+pub fn run(
+    action: ReportAction,
+    config: &Config,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Status {
+    match action {
+        ReportAction::Html {
+            library_path,
+            output,
+        } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            match html_report(&library, &output, config.lyrics.sidecar, summary) {
+                Ok(()) => {
+                    say!("Report written to {}", output.join("index.html").display());
+                    Status::Ok
+                }
+                Err(e) => {
+                    error!("Could not write report to {}: {}", output.display(), e);
+                    Status::Failure
+                }
+            }
+        }
+        ReportAction::Paths {
+            library_path,
+            max_length,
+            max_depth,
+            prefix,
+        } => {
+            let library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            long_paths(&library, prefix.as_deref(), max_length, max_depth, summary)
+        }
+    }
+}
+
+/// List the tracks whose path inside the library, under `prefix` on the device, is longer than
+/// `max_length` characters or has more than `max_depth` folders. Each line starts with the ID of
+/// the finding, which only depends on the path inside the library.
+fn long_paths(
+    library: &DirtyLibrary,
+    prefix: Option<&Path>,
+    max_length: usize,
+    max_depth: usize,
+    summary: &mut Summary,
+) -> Status {
+    let mut findings = Vec::new();
+    for path in library.tracks.iter().filter_map(|t| t.file_path.as_deref()) {
+        let relative = path.strip_prefix(&library.path).unwrap_or(path);
+        let device_path = match prefix {
+            Some(prefix) => prefix.join(relative),
+            None => relative.to_path_buf(),
+        };
+        let length = device_path.to_string_lossy().chars().count();
+        let depth = device_path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .count()
+            .saturating_sub(1);
+
+        let mut problems = Vec::new();
+        if length > max_length {
+            problems.push(format!("{} characters", length));
+        }
+        if depth > max_depth {
+            problems.push(format!("{} folders deep", depth));
+        }
+        if problems.is_empty() {
+            continue;
+        }
+        summary.matched += 1;
+        let id = finding_id("long-path", &relative.to_string_lossy());
+        findings.push((device_path, id, problems));
+    }
+    findings.sort();
+    for (device_path, id, problems) in findings {
+        say!(
+            "{} {} ({})",
+            paint(id, Style::Dim),
+            device_path.display(),
+            problems.join(", ")
+        );
+    }
+
+    if summary.matched == 0 {
+        say!(
+            "No path is longer than {} characters or deeper than {} folders",
+            max_length,
+            max_depth
+        );
+        Status::NothingMatched
+    } else {
+        Status::Ok
+    }
+}
+
+/// Write "index.html" and the album covers into `output`.
+fn html_report(
+    library: &DirtyLibrary,
+    output: &Path,
+    naming: SidecarNaming,
+    summary: &mut Summary,
+) -> std::io::Result<()> {
+    fs::create_dir_all(output.join(COVERS_DIR))?;
+
+    let mut artists: BTreeMap<String, Vec<AlbumCard>> = BTreeMap::new();
+    for ((folder, album), tracks) in group_by_album(&library.tracks) {
+        summary.matched += 1;
+        let artist = album_artist(&tracks);
+        let relative = folder.strip_prefix(&library.path).unwrap_or(folder);
+        let id = finding_id("album", &format!("{}\n{}", relative.display(), album));
+
+        let (cover, cover_info) = copy_cover(folder, &tracks, output, &id).unzip();
+        let card = AlbumCard {
+            id,
+            title: album.to_string(),
+            year: tracks.iter().find_map(|t| t.year),
+            country: album_country(&tracks),
+            tracks: tracks.len(),
+            cover,
+            cover_info: cover_info.flatten(),
+            quality: tracks
+                .iter()
+                .map(|t| Quality::of(t))
+                .min()
+                .unwrap_or(Quality::Lossy(None)),
+            missing_lyrics: tracks
+                .iter()
+                .filter(|t| {
+                    !t.embedded_lyrics
+                        && t.file_path
+                            .as_deref()
+                            .is_some_and(|path| lyrics::sidecars(path, naming).is_empty())
+                })
+                .count(),
+        };
+        artists.entry(artist.to_string()).or_default().push(card);
+    }
+    for albums in artists.values_mut() {
+        albums.sort_by(|a, b| (a.year, &a.title, &a.id).cmp(&(b.year, &b.title, &b.id)));
+    }
+    // Paths inside the library, so that the report reads the same wherever it is mounted
+    let unsupported: Vec<_> = library
+        .unsupported
+        .iter()
+        .map(|(path, reason)| {
+            let relative = path.strip_prefix(&library.path).unwrap_or(path);
+            (relative.to_path_buf(), *reason)
+        })
+        .collect();
+
+    write_file(&output.join("index.html"), |out| {
+        write_html(out, "Music library", &artists, &unsupported)
+    })
+}
+
+/// Copy the cover of the album, from its folder or else embedded in a track, into the report.
+/// The copy is named after the album ID. Returns its path relative to the report, and its format
+/// and size read from its header.
+fn copy_cover(
+    folder: &Path,
+    tracks: &[&DirtyTrack],
+    output: &Path,
+    id: &str,
+) -> Option<(String, Option<ImageInfo>)> {
+    let (data, extension) = album_cover(folder, tracks)?;
+    let name = format!("{}/{}.{}", COVERS_DIR, id, extension);
+    match write_file(&output.join(&name), |out| out.write_all(&data)) {
+        Ok(()) => Some((name, image_info(&data))),
+        Err(e) => {
+            error!("Could not copy the cover of {}: {}", folder.display(), e);
+            None
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use crate::{
+    Status,
+    commands::{self, load_library},
+    config::Profile,
+    hooks::Hooks,
+    output::{Style, paint, say},
+    summary::Summary,
+};
+
+pub fn run(
+    library_path: Option<PathBuf>,
+    follow_moves: bool,
+    profile: &Profile,
+    hooks: &Hooks,
+    summary: &mut Summary,
+) -> Status {
+    let library = match load_library(library_path, profile, hooks, summary) {
+        Ok(library) => library,
+        Err(status) => return status,
+    };
+
+    summary.matched = library.added.len() + library.changed.len() + library.moved.len();
+    summary.skipped = library.tracks.len() - summary.matched;
+    for track in &library.tracks {
+        say!("{:?}", track);
+    }
+    say!(
+        "Total tracks found in {}: {}",
+        library.path.display(),
+        library.tracks.len()
+    );
+    if !library.unsupported.is_empty() {
+        say!(
+            "{}",
+            paint(
+                "Unsupported files, skipped until they change:",
+                Style::Warning
+            )
+        );
+        for (path, reason) in &library.unsupported {
+            say!("  {} ({})", path.display(), reason);
+        }
+    }
+
+    let changes = commands::follow_moves(&library.path, follow_moves);
+    if changes > 0 && !follow_moves {
+        say!("Run with --follow-moves to make these changes");
+    }
+
+    if library.tracks.is_empty() {
+        Status::NothingMatched
+    } else if library.tracks.iter().any(|t| t.read_error.is_some()) {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
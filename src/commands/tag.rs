@@ -0,0 +1,657 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use lofty::{
+    file::TaggedFileExt,
+    tag::{ItemKey, Tag, TagItem},
+};
+use log::{debug, error, warn};
+
+use crate::{
+    Status, cancelled,
+    cli::TagAction,
+    commands::{filter_albums, filter_tracks, load_library},
+    config::{Config, Profile},
+    fs::write_file,
+    hooks::Hooks,
+    http, journal,
+    library::group_by_album,
+    musicbrainz::MusicBrainz,
+    output::{Style, confirm, paint, say},
+    summary::Summary,
+    tag::{edit_tag, item_name, key_matches, preview_tag_edit, vorbis_vendor},
+    tagdiff::{self, TagDiff},
+    track::DirtyTrack,
+};
+
+pub fn run(
+    action: TagAction,
+    config: &Config,
+    profile: &Profile,
+    hooks: &Hooks,
+    offline: bool,
+    summary: &mut Summary,
+) -> Status {
+    match action {
+        TagAction::Renumber {
+            library_path,
+            filter,
+            pad,
+            sequential,
+            rename,
+            all,
+            diff,
+            apply,
+            yes,
+        } => {
+            let options = RenumberOptions {
+                pad,
+                sequential,
+                rename,
+                all,
+                diff,
+            };
+            if library_path.as_deref() == Some(Path::new("-")) {
+                error!("Renumbering needs whole albums, give their folder rather than \"-\"");
+                return Status::Failure;
+            }
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_albums(&mut library.tracks, filter.as_ref());
+            renumber(&library.tracks, &options, apply || yes, yes, summary)
+        }
+        TagAction::Isrc {
+            library_path,
+            filter,
+            diff,
+            apply,
+            yes,
+        } => {
+            if offline {
+                error!("ISRCs are looked up on MusicBrainz, which is not possible offline");
+                return Status::Failure;
+            }
+            let agent = match http::agent(config) {
+                Ok(agent) => agent,
+                Err(e) => {
+                    error!("{}", e);
+                    return Status::Failure;
+                }
+            };
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            let musicbrainz = MusicBrainz::new(agent);
+            backfill_isrcs(
+                &library.tracks,
+                musicbrainz,
+                diff.as_deref(),
+                apply || yes,
+                yes,
+                summary,
+            )
+        }
+        TagAction::Strip {
+            library_path,
+            filter,
+            keys,
+            private_frames,
+            diff,
+            apply,
+            yes,
+        } => {
+            let keys = keys.unwrap_or_else(|| config.tag.strip_keys.clone());
+            if keys.is_empty() && !private_frames {
+                error!("Nothing to strip, pass --keys, --private-frames or set tag.strip_keys");
+                return Status::Failure;
+            }
+            let mut library = match load_library(library_path, profile, hooks, summary) {
+                Ok(library) => library,
+                Err(status) => return status,
+            };
+            filter_tracks(&mut library.tracks, filter.as_ref());
+            strip(
+                &library.tracks,
+                &keys,
+                private_frames,
+                diff.as_deref(),
+                apply || yes,
+                yes,
+                summary,
+            )
+        }
+        TagAction::Apply { diff, yes } => apply_diff(&diff, yes, summary),
+    }
+}
+
+struct RenumberOptions {
+    pad: usize,
+    sequential: bool,
+    rename: bool,
+    all: bool,
+    diff: Option<PathBuf>,
+}
+
+/// A planned change to one track of an album.
+struct Renumbering<'a> {
+    path: &'a Path,
+    old_number: Option<u32>,
+    old_total: Option<u32>,
+    number: u32,
+    total: u32,
+    new_path: Option<PathBuf>,
+}
+
+/// Make track numbers consistent per album, with totals matching the actual track count of each
+/// disc.
+fn renumber(
+    tracks: &[DirtyTrack],
+    options: &RenumberOptions,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let albums = group_by_album(tracks);
+
+    let mut failed = 0;
+    let mut diffs = Vec::new();
+    for ((folder, album), mut tracks) in albums {
+        tracks.sort_by_key(|t| {
+            (
+                t.disc_number.unwrap_or(1),
+                t.track_number.unwrap_or(u32::MAX),
+                t.file_path.clone(),
+            )
+        });
+
+        let mut totals: HashMap<u32, u32> = HashMap::new();
+        for track in &tracks {
+            *totals.entry(track.disc_number.unwrap_or(1)).or_default() += 1;
+        }
+
+        let mut positions: HashMap<u32, u32> = HashMap::new();
+        let mut changes = Vec::new();
+        for track in &tracks {
+            let Some(path) = track.file_path.as_deref() else {
+                continue;
+            };
+            let disc = track.disc_number.unwrap_or(1);
+            let position = positions.entry(disc).or_default();
+            *position += 1;
+
+            let number = if options.sequential {
+                *position
+            } else if let Some(number) = track.track_number {
+                number
+            } else {
+                continue;
+            };
+            let total = totals[&disc];
+            let new_path = options
+                .rename
+                .then(|| {
+                    renamed(
+                        path,
+                        &pad(number, options.pad),
+                        track.track_number,
+                        track.title.as_deref(),
+                    )
+                })
+                .flatten()
+                .filter(|new_path| new_path != path);
+
+            if options.all
+                || track.track_number != Some(number)
+                || track.track_total != Some(total)
+                || new_path.is_some()
+            {
+                changes.push(Renumbering {
+                    path,
+                    old_number: track.track_number,
+                    old_total: track.track_total,
+                    number,
+                    total,
+                    new_path,
+                });
+            }
+        }
+
+        if changes.is_empty() {
+            continue;
+        }
+        summary.matched += 1;
+
+        say!("{} ({})", album, folder.display());
+        for change in &changes {
+            say!(
+                "  {}: {}/{} -> {}/{}",
+                change
+                    .path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy(),
+                change.old_number.map_or("-".to_string(), |n| n.to_string()),
+                change.old_total.map_or("-".to_string(), |n| n.to_string()),
+                change.number,
+                change.total
+            );
+            if let Some(new_path) = &change.new_path {
+                say!(
+                    "    rename to {}",
+                    new_path.file_name().unwrap_or_default().to_string_lossy()
+                );
+            }
+        }
+        if options.diff.is_some() {
+            for change in &changes {
+                preview(
+                    change.path,
+                    |tag| set_numbers(tag, change, options.pad),
+                    &mut diffs,
+                );
+            }
+        }
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm("Apply these changes?") {
+            summary.skipped += 1;
+            continue;
+        }
+
+        for change in changes {
+            if let Err(e) = apply_renumbering(&change, options.pad) {
+                error!("Could not update {}: {}", change.path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            } else {
+                summary.modified += 1;
+            }
+        }
+    }
+
+    if let Some(output) = &options.diff
+        && !write_diff(output, &diffs)
+    {
+        return Status::Failure;
+    }
+    if summary.matched == 0 {
+        say!("Track numbers are already consistent");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Look up the ISRC of tracks without one by their MusicBrainz recording ID, and write it. When
+/// a recording has several, the first one is used.
+fn backfill_isrcs(
+    tracks: &[DirtyTrack],
+    mut musicbrainz: MusicBrainz,
+    diff: Option<&Path>,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let mut failed = 0;
+    let mut diffs = Vec::new();
+    for track in tracks.iter().filter(|t| t.isrc.is_none()) {
+        if cancelled() {
+            error!("Cancelled, {} ISRCs written", summary.modified);
+            return Status::Failure;
+        }
+        let (Some(path), Some(id)) = (track.file_path.as_deref(), track.recording_id.as_deref())
+        else {
+            continue;
+        };
+        let isrcs = match musicbrainz.recording_isrcs(id) {
+            Ok(isrcs) => isrcs,
+            Err(e) => {
+                error!("Could not look up recording {}: {}", id, e);
+                summary.errors += 1;
+                failed += 1;
+                continue;
+            }
+        };
+        let Some(isrc) = isrcs.first() else {
+            debug!("No ISRC on MusicBrainz for {}", path.display());
+            continue;
+        };
+        summary.matched += 1;
+        if isrcs.len() > 1 {
+            say!("{}: {} (of {})", path.display(), isrc, isrcs.join(", "));
+        } else {
+            say!("{}: {}", path.display(), isrc);
+        }
+        let set_isrc = |tag: &mut Tag| tag.insert_text(ItemKey::Isrc, isrc.clone());
+        if diff.is_some() {
+            preview(path, set_isrc, &mut diffs);
+        }
+
+        if !apply {
+            continue;
+        }
+        if !yes && !confirm("Write this ISRC?") {
+            summary.skipped += 1;
+            continue;
+        }
+        match edit_tag(path, set_isrc) {
+            Ok(_) => summary.modified += 1,
+            Err(e) => {
+                error!("Could not update {}: {}", path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            }
+        }
+    }
+
+    if let Some(output) = diff
+        && !write_diff(output, &diffs)
+    {
+        return Status::Failure;
+    }
+    if summary.matched == 0 && failed == 0 {
+        say!("No missing ISRC found on MusicBrainz");
+        Status::NothingMatched
+    } else if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Write the numbers as separate number and total fields, which also gets rid of "1/12" style
+/// values.
+fn apply_renumbering(change: &Renumbering, width: usize) -> Result<(), String> {
+    edit_tag(change.path, |tag| set_numbers(tag, change, width)).map_err(|e| e.to_string())?;
+
+    if let Some(new_path) = &change.new_path {
+        if new_path.exists() {
+            warn!("Not renaming over existing file {}", new_path.display());
+            return Ok(());
+        }
+        fs::rename(change.path, new_path).map_err(|e| e.to_string())?;
+        journal::record_rename(change.path, new_path);
+    }
+    Ok(())
+}
+
+fn set_numbers(tag: &mut Tag, change: &Renumbering, width: usize) -> bool {
+    tag.insert_text(ItemKey::TrackNumber, pad(change.number, width));
+    tag.insert_text(ItemKey::TrackTotal, change.total.to_string());
+    true
+}
+
+fn pad(number: u32, width: usize) -> String {
+    format!("{:0width$}", number, width = width)
+}
+
+/// File name with its leading track number replaced by `number`, e.g. "1. Song.flac" to
+/// "01 Song.flac". Leading digits only count as the track number when they equal `old_number` and
+/// a separator follows them, so "99 Luftballons.flac" keeps its digits. Otherwise, or if nothing
+/// is left of the name, the name is made from the title.
+fn renamed(
+    path: &Path,
+    number: &str,
+    old_number: Option<u32>,
+    title: Option<&str>,
+) -> Option<PathBuf> {
+    const SEPARATORS: [char; 4] = [' ', '.', '-', '_'];
+    let stem = path.file_stem()?.to_str()?;
+    let after_digits = stem.trim_start_matches(|c: char| c.is_ascii_digit());
+    let digits = &stem[..stem.len() - after_digits.len()];
+    let rest = (old_number.is_some()
+        && digits.parse::<u32>().ok() == old_number
+        && after_digits.starts_with(SEPARATORS))
+    .then(|| after_digits.trim_start_matches(SEPARATORS))
+    .filter(|rest| !rest.is_empty());
+    let rest = match rest {
+        Some(rest) => rest.to_string(),
+        None => title?.replace(['/', '\\'], "-"),
+    };
+
+    let mut name = format!("{} {}", number, rest);
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        name.push('.');
+        name.push_str(extension);
+    }
+    Some(path.with_file_name(name))
+}
+
+/// Remove the given keys, and optionally every non-standard item, from the tags of all tracks.
+/// Everything that would be removed is listed before asking for confirmation.
+fn strip(
+    tracks: &[DirtyTrack],
+    keys: &[String],
+    private_frames: bool,
+    diff: Option<&Path>,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let should_strip = |item: &TagItem, tag_type, vendor: Option<&str>| {
+        if *item.key() == ItemKey::EncoderSoftware && item.value().text() == vendor {
+            return false;
+        }
+        (private_frames && matches!(item.key(), ItemKey::Unknown(_)))
+            || keys
+                .iter()
+                .any(|pattern| key_matches(pattern, item.key(), tag_type))
+    };
+
+    let strip_items = |tag: &mut Tag, vendor: Option<&str>| {
+        let tag_type = tag.tag_type();
+        tag.retain(|item| !should_strip(item, tag_type, vendor));
+        true
+    };
+
+    let mut to_strip = Vec::new();
+    let mut diffs = Vec::new();
+    for path in tracks.iter().filter_map(|t| t.file_path.as_deref()) {
+        let Ok(tagged_file) = lofty::read_from_path(path) else {
+            continue;
+        };
+        let Some(tag) = tagged_file.primary_tag() else {
+            continue;
+        };
+
+        let vendor = vorbis_vendor(path);
+        let removed: Vec<String> = tag
+            .items()
+            .filter(|item| should_strip(item, tag.tag_type(), vendor.as_deref()))
+            .map(|item| {
+                format!(
+                    "{}={}",
+                    item_name(item.key(), tag.tag_type()),
+                    item.value().text().unwrap_or("<binary>")
+                )
+            })
+            .collect();
+        if removed.is_empty() {
+            continue;
+        }
+
+        say!("{}", paint(path.display(), Style::Bold));
+        for item in &removed {
+            say!("  {}", paint(format!("- {}", item), Style::Error));
+        }
+        if diff.is_some() {
+            preview(path, |tag| strip_items(tag, vendor.as_deref()), &mut diffs);
+        }
+        to_strip.push(path);
+    }
+
+    if let Some(output) = diff
+        && !write_diff(output, &diffs)
+    {
+        return Status::Failure;
+    }
+    summary.matched = to_strip.len();
+    if to_strip.is_empty() {
+        say!("Nothing to strip");
+        return Status::NothingMatched;
+    }
+    if !apply {
+        return Status::Ok;
+    }
+    if !yes
+        && !confirm(&format!(
+            "Strip these fields from {} files?",
+            to_strip.len()
+        ))
+    {
+        summary.skipped = to_strip.len();
+        return Status::Ok;
+    }
+
+    let mut failed = 0;
+    for path in to_strip {
+        let size_before = fs::metadata(path).map_or(0, |m| m.len());
+        let vendor = vorbis_vendor(path);
+        match edit_tag(path, |tag| strip_items(tag, vendor.as_deref())) {
+            Ok(_) => {
+                summary.modified += 1;
+                let size_after = fs::metadata(path).map_or(0, |m| m.len());
+                summary.bytes_freed += size_before.saturating_sub(size_after);
+            }
+            Err(e) => {
+                error!("Could not update {}: {}", path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+/// Record the changes `edit` would make to a file, for --diff.
+fn preview(path: &Path, edit: impl FnOnce(&mut Tag) -> bool, diffs: &mut Vec<TagDiff>) {
+    match preview_tag_edit(path, edit) {
+        Ok((before, after)) => diffs.extend(TagDiff::between(path, &before, &after)),
+        Err(e) => warn!("Could not read {}: {}", path.display(), e),
+    }
+}
+
+/// Write diffs to `output`, or stdout for "-". Returns false if the file could not be written.
+fn write_diff(output: &Path, diffs: &[TagDiff]) -> bool {
+    let text = tagdiff::format(diffs);
+    if output == Path::new("-") {
+        for line in text.lines() {
+            let style = if line.starts_with("--- ") || line.starts_with("+++ ") {
+                Style::Bold
+            } else if line.starts_with('-') {
+                Style::Error
+            } else {
+                Style::Success
+            };
+            println!("{}", paint(line, style));
+        }
+        return true;
+    }
+    if let Err(e) = write_file(output, |out| out.write_all(text.as_bytes())) {
+        error!("Could not write {}: {}", output.display(), e);
+        return false;
+    }
+    say!(
+        "Diff of {} files written to {}",
+        diffs.len(),
+        output.display()
+    );
+    true
+}
+
+/// Apply a diff written with --diff. Every file is checked first: those whose tags no longer hold
+/// the old values of the diff are reported and left alone.
+fn apply_diff(path: &Path, yes: bool, summary: &mut Summary) -> Status {
+    let diffs = match fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| tagdiff::parse(&text))
+    {
+        Ok(diffs) => diffs,
+        Err(e) => {
+            error!("Could not read diff {}: {}", path.display(), e);
+            return Status::Failure;
+        }
+    };
+
+    let mut failed = 0;
+    let mut ready = Vec::new();
+    for diff in &diffs {
+        match preview_tag_edit(&diff.path, |_| false) {
+            Ok((current, _)) if diff.applies_to(&current) => {
+                say!("{}", diff.path.display());
+                let shown = |values: &Vec<String>| {
+                    if values.is_empty() {
+                        "-".to_string()
+                    } else {
+                        values.join("; ")
+                    }
+                };
+                for (field, (before, after)) in &diff.fields {
+                    say!("  {}: {} -> {}", field, shown(before), shown(after));
+                }
+                ready.push(diff);
+            }
+            Ok(_) => {
+                error!(
+                    "{} changed since the diff was written, skipping it",
+                    diff.path.display()
+                );
+                summary.errors += 1;
+                failed += 1;
+            }
+            Err(e) => {
+                error!("Could not read {}: {}", diff.path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            }
+        }
+    }
+
+    summary.matched = ready.len();
+    if ready.is_empty() {
+        if failed > 0 {
+            return Status::Failure;
+        }
+        say!("Nothing to apply");
+        return Status::NothingMatched;
+    }
+    if !yes && !confirm(&format!("Apply these changes to {} files?", ready.len())) {
+        summary.skipped = ready.len();
+        return Status::Ok;
+    }
+
+    for diff in ready {
+        match edit_tag(&diff.path, |tag| diff.apply(tag)) {
+            Ok(true) => summary.modified += 1,
+            Ok(false) => summary.skipped += 1,
+            Err(e) => {
+                error!("Could not update {}: {}", diff.path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
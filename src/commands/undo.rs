@@ -0,0 +1,174 @@
+use std::{
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use log::error;
+
+use crate::{
+    Status,
+    fs::{civil_from_unix, quick_hash, restore_file, write_file},
+    journal::{self, Action, Entry},
+    output::{Style, confirm, paint, say},
+    summary::Summary,
+};
+
+/// How a journaled change is undone.
+enum Undo {
+    /// Move the file back from where it was kept or renamed to, checking its hash when known.
+    Move { from: PathBuf, hash: Option<String> },
+    /// Write back the content of a removed text file.
+    Write(String),
+    /// The file was removed without a copy.
+    Lost,
+}
+
+/// Put back the files deleted or moved by one run, the last one by default, latest change first.
+pub fn run(
+    journal: Option<PathBuf>,
+    run: Option<String>,
+    apply: bool,
+    yes: bool,
+    summary: &mut Summary,
+) -> Status {
+    let entries = match &journal {
+        Some(path) => journal::read_from(path),
+        None => journal::read(),
+    };
+    let entries = match entries {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!("Could not read the journal: {}", e);
+            return Status::Failure;
+        }
+    };
+
+    let undoable = |entry: &Entry| matches!(entry.action, Action::Delete | Action::Rename);
+    let Some(run) = run.or_else(|| {
+        entries
+            .iter()
+            .rev()
+            .filter(|entry| undoable(entry))
+            .find_map(|entry| entry.run.clone())
+    }) else {
+        say!("No run in the journal deleted or moved files");
+        return Status::NothingMatched;
+    };
+    let changes: Vec<(&Entry, Undo)> = entries
+        .iter()
+        .rev()
+        .filter(|entry| entry.run.as_ref() == Some(&run) && undoable(entry))
+        .map(|entry| (entry, undo_of(entry)))
+        .collect();
+    let Some((first, _)) = changes.last() else {
+        say!("Run {} deleted or moved no file", run);
+        return Status::NothingMatched;
+    };
+
+    summary.matched = changes.len();
+    let (year, month, day, hour, minute) = civil_from_unix(first.time);
+    say!(
+        "Run {} of `muman {}` on {}-{:02}-{:02} {:02}:{:02}",
+        run,
+        first.command,
+        year,
+        month,
+        day,
+        hour,
+        minute
+    );
+    for (entry, undo) in &changes {
+        match undo {
+            Undo::Move { from, .. } => {
+                say!("  {} <- {}", entry.path.display(), from.display())
+            }
+            Undo::Write(_) => say!(
+                "  {} <- {}",
+                entry.path.display(),
+                paint("content in the journal", Style::Dim)
+            ),
+            Undo::Lost => say!(
+                "  {} {}",
+                entry.path.display(),
+                paint("(removed without a copy)", Style::Warning)
+            ),
+        }
+    }
+
+    if !apply {
+        return Status::Ok;
+    }
+    if !yes && !confirm("Undo these changes?") {
+        summary.skipped += changes.len();
+        return Status::Ok;
+    }
+
+    let mut failed = 0;
+    for (entry, undo) in &changes {
+        if matches!(undo, Undo::Lost) {
+            summary.skipped += 1;
+            continue;
+        }
+        match restore(&entry.path, undo) {
+            Ok(()) => summary.modified += 1,
+            Err(e) => {
+                error!("Could not restore {}: {}", entry.path.display(), e);
+                summary.errors += 1;
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        Status::PartialFailure
+    } else {
+        Status::Ok
+    }
+}
+
+fn undo_of(entry: &Entry) -> Undo {
+    match (&entry.after, &entry.before) {
+        (Some(after), _) => Undo::Move {
+            from: PathBuf::from(after),
+            hash: entry.hash.clone(),
+        },
+        (None, Some(content)) if entry.action == Action::Delete => Undo::Write(content.clone()),
+        _ => Undo::Lost,
+    }
+}
+
+/// Undo one change, never overwriting a file that took the place of the old one since.
+fn restore(path: &Path, undo: &Undo) -> io::Result<()> {
+    if path.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "another file is there now",
+        ));
+    }
+    match undo {
+        Undo::Move { from, hash } => {
+            if !from.exists() {
+                return Err(io::Error::other(format!("{} is gone", from.display())));
+            }
+            if let Some(hash) = hash
+                && journal::format_hash(quick_hash(from)?) != *hash
+            {
+                return Err(io::Error::other(format!(
+                    "{} is not the same file anymore",
+                    from.display()
+                )));
+            }
+            restore_file(from, path)?;
+            journal::record_rename(from, path);
+        }
+        Undo::Write(content) => {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            write_file(path, |out| out.write_all(content.as_bytes()))?;
+            journal::record_write(path, None, Some(content.clone()));
+        }
+        Undo::Lost => {}
+    }
+    Ok(())
+}
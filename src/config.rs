@@ -0,0 +1,467 @@
+use std::{collections::HashMap, fmt, fs, io::ErrorKind, path::PathBuf};
+
+use log::{debug, warn};
+use serde::Deserialize;
+
+use crate::{
+    ALLOWED_EXTENSIONS,
+    fs::Deletion,
+    hooks::{EventKind, Hooks, ShellHook, Webhook, WebhookHook},
+    http,
+    lyrics::SidecarNaming,
+};
+
+/// Contents of `$XDG_CONFIG_HOME/muman/config.toml` or `$MUMAN_CONFIG`, every field is optional.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    /// Profile used when `--profile` is not given.
+    pub default_profile: Option<String>,
+    /// Defaults of the options of every command.
+    #[serde(flatten)]
+    pub options: CommandOptions,
+    /// Options of one command, overriding those above, as `[commands.<name>]` sections.
+    pub commands: HashMap<String, CommandOptions>,
+    pub profiles: HashMap<String, Profile>,
+    /// Shell commands to run on each event, e.g. `track-added = ["notify-send $MUMAN_PATH"]`.
+    pub hooks: HashMap<EventKind, Vec<String>>,
+    /// URLs to notify of events, as `[[webhooks]]` entries with `url`, `format` ("json", "ntfy"
+    /// or "discord") and `events`.
+    pub webhooks: Vec<Webhook>,
+    pub tag: TagConfig,
+    pub lyrics: LyricsConfig,
+    pub http: HttpConfig,
+    /// Playlist export profiles, selected with `--export`.
+    pub exports: HashMap<String, ExportProfile>,
+}
+
+/// Options shared by every command, e.g. `io_jobs = 2` at the top of the config or in a
+/// `[commands.backup]` section. Also holds the values given on the command line.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct CommandOptions {
+    pub jobs: Option<usize>,
+    pub io_jobs: Option<usize>,
+    pub offline: Option<bool>,
+    /// File extensions to scan, e.g. `["flac", "mp3"]`.
+    pub extensions: Option<Vec<String>>,
+    /// Move deleted files to the trash, `false` to remove them even if the top of the config says
+    /// otherwise.
+    pub trash: Option<bool>,
+    /// Move deleted files under this folder. Takes precedence over `trash`.
+    pub quarantine: Option<PathBuf>,
+}
+
+impl CommandOptions {
+    /// What deleting a file does with these options, `None` when they do not say.
+    fn deletion(&self) -> Option<Deletion> {
+        match (&self.quarantine, self.trash) {
+            (Some(folder), _) => Some(Deletion::Quarantine(folder.clone())),
+            (None, Some(true)) => Some(Deletion::Trash),
+            (None, Some(false)) => Some(Deletion::Remove),
+            (None, None) => None,
+        }
+    }
+}
+
+/// Where the effective value of a setting comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Source {
+    CommandLine,
+    /// An environment variable such as `MUMAN_JOBS`.
+    Environment(&'static str),
+    /// A `[commands.<name>]` section of the config.
+    CommandSection(String),
+    ConfigFile,
+    Profile,
+    Default,
+}
+
+/// Effective value of a setting and where it comes from.
+pub struct Setting<T> {
+    pub value: T,
+    pub source: Source,
+}
+
+/// Options of a command resolved from the command line, the environment, the command's config
+/// section, the top of the config, then the built-in defaults, the first one set winning.
+pub struct Settings {
+    /// `None` for the number of CPUs.
+    pub jobs: Setting<Option<usize>>,
+    /// `None` for `DEFAULT_IO_JOBS`.
+    pub io_jobs: Setting<Option<usize>>,
+    /// Library used when a command is given none: `$MUMAN_LIBRARY`, then the profile's. The
+    /// profile's comes first when it was picked with `--profile`.
+    pub library: Setting<Option<PathBuf>>,
+    /// Skip everything that needs the network, such as MusicBrainz lookups and webhooks. `None`
+    /// for online.
+    pub offline: Setting<Option<bool>>,
+    /// File extensions to scan, from the profile before the top of the config. `None` for
+    /// `ALLOWED_EXTENSIONS`.
+    pub extensions: Setting<Option<Vec<String>>>,
+    /// What deleting a file does. `None` to remove it.
+    pub deletion: Setting<Option<Deletion>>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct TagConfig {
+    /// Keys removed by `tag strip` when `--keys` is not given.
+    pub strip_keys: Vec<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct LyricsConfig {
+    /// Sidecar lyrics file names: "replace" for "Song.lrc", "append" for "Song.flac.lrc".
+    pub sidecar: SidecarNaming,
+}
+
+/// Network settings of the `[http]` section, used by MusicBrainz lookups and webhooks.
+#[derive(Deserialize, Default)]
+#[serde(default)]
+pub struct HttpConfig {
+    /// Proxy URL, e.g. "http://proxy.example.com:3128" or "socks5://localhost:1080".
+    pub proxy: Option<String>,
+    /// Seconds before a request is given up, 30 by default.
+    pub timeout: Option<u64>,
+    /// User-Agent sent with every request instead of muman's own.
+    pub user_agent: Option<String>,
+}
+
+/// How playlists are written for a given player, e.g. a car head unit that only reads
+/// Windows-style files.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct ExportProfile {
+    /// CRLF line endings instead of LF.
+    pub crlf: bool,
+    /// Backslash path separators.
+    pub backslashes: bool,
+    /// Start M3U playlists with a UTF-8 byte order mark.
+    pub bom: bool,
+    /// Write paths relative to the folder of the playlist.
+    pub relative: bool,
+    /// Replace the library folder with this prefix in paths, e.g. `E:\Music`. Takes precedence
+    /// over `relative`.
+    pub root: Option<String>,
+}
+
+/// A named library setup, so the same commands can target different collections.
+#[derive(Deserialize, Default, Clone)]
+#[serde(default)]
+pub struct Profile {
+    pub library: Option<PathBuf>,
+    pub extensions: Option<Vec<String>>,
+}
+
+impl Config {
+    /// Load the config file, falling back to the defaults if it is missing. A config that cannot
+    /// be read or parsed is an error: running without it could remove files its deletion setting
+    /// would have kept, or work on another library.
+    pub fn load() -> Result<Self, String> {
+        let Some(path) = config_path() else {
+            return Ok(Config::default());
+        };
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                debug!("No config read from {}: {}", path.display(), e);
+                return Ok(Config::default());
+            }
+            Err(e) => {
+                return Err(format!(
+                    "Could not read config file {}: {}",
+                    path.display(),
+                    e
+                ));
+            }
+        };
+        toml::from_str(&content)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    /// Resolve the requested profile, or the default one. Without either an empty profile is
+    /// returned so the CLI arguments alone decide.
+    pub fn profile(&self, name: Option<&str>) -> Option<Profile> {
+        match name.or(self.default_profile.as_deref()) {
+            Some(name) => self.profiles.get(name).cloned(),
+            None => Some(Profile::default()),
+        }
+    }
+}
+
+impl Config {
+    /// Resolve the options of `command`, a top-level command name such as "backup", with the
+    /// values given on the command line first. A profile picked with `--profile` has its library
+    /// ranked before `$MUMAN_LIBRARY`.
+    pub fn settings(
+        &self,
+        command: Option<&str>,
+        cli: &CommandOptions,
+        profile: &Profile,
+        profile_given: bool,
+    ) -> Settings {
+        let section = command.and_then(|command| self.commands.get(command));
+        let section_source = Source::CommandSection(command.unwrap_or_default().to_string());
+        Settings {
+            jobs: resolve([
+                (cli.jobs, Source::CommandLine),
+                (env_var("MUMAN_JOBS"), Source::Environment("MUMAN_JOBS")),
+                (section.and_then(|s| s.jobs), section_source.clone()),
+                (self.options.jobs, Source::ConfigFile),
+            ]),
+            io_jobs: resolve([
+                (cli.io_jobs, Source::CommandLine),
+                (section.and_then(|s| s.io_jobs), section_source.clone()),
+                (self.options.io_jobs, Source::ConfigFile),
+            ]),
+            library: if profile_given {
+                resolve([
+                    (profile.library.clone(), Source::Profile),
+                    (
+                        env_var("MUMAN_LIBRARY"),
+                        Source::Environment("MUMAN_LIBRARY"),
+                    ),
+                ])
+            } else {
+                resolve([
+                    (
+                        env_var("MUMAN_LIBRARY"),
+                        Source::Environment("MUMAN_LIBRARY"),
+                    ),
+                    (profile.library.clone(), Source::Profile),
+                ])
+            },
+            offline: resolve([
+                (cli.offline, Source::CommandLine),
+                (
+                    env_var("MUMAN_OFFLINE"),
+                    Source::Environment("MUMAN_OFFLINE"),
+                ),
+                (section.and_then(|s| s.offline), section_source.clone()),
+                (self.options.offline, Source::ConfigFile),
+            ]),
+            extensions: resolve([
+                (cli.extensions.clone(), Source::CommandLine),
+                (
+                    section.and_then(|s| s.extensions.clone()),
+                    section_source.clone(),
+                ),
+                (profile.extensions.clone(), Source::Profile),
+                (self.options.extensions.clone(), Source::ConfigFile),
+            ]),
+            deletion: resolve([
+                (cli.deletion(), Source::CommandLine),
+                (section.and_then(CommandOptions::deletion), section_source),
+                (self.options.deletion(), Source::ConfigFile),
+            ]),
+        }
+    }
+
+    /// Resolve an export profile from the config. "windows" is built in unless the config
+    /// redefines it.
+    pub fn export(&self, name: &str) -> Option<ExportProfile> {
+        self.exports
+            .get(name)
+            .cloned()
+            .or_else(|| (name == "windows").then(ExportProfile::windows))
+    }
+
+    /// Hooks of the config. Webhooks are left out when offline, and otherwise share one HTTP
+    /// client.
+    pub fn hooks(&self, offline: bool) -> Result<Hooks, String> {
+        let mut hooks = Hooks::default();
+        for (kind, commands) in &self.hooks {
+            for command in commands {
+                hooks.register(Box::new(ShellHook {
+                    kind: *kind,
+                    command: command.clone(),
+                }));
+            }
+        }
+        if self.webhooks.is_empty() {
+            return Ok(hooks);
+        }
+        if offline {
+            debug!("Offline, webhooks are not called");
+            return Ok(hooks);
+        }
+        let agent = http::agent(self)?;
+        for webhook in &self.webhooks {
+            hooks.register(Box::new(WebhookHook {
+                webhook: webhook.clone(),
+                agent: agent.clone(),
+            }));
+        }
+        Ok(hooks)
+    }
+}
+
+impl ExportProfile {
+    pub fn windows() -> Self {
+        ExportProfile {
+            crlf: true,
+            backslashes: true,
+            bom: true,
+            relative: true,
+            root: None,
+        }
+    }
+}
+
+impl Profile {
+    /// The library path given on the command line takes precedence over the profile's.
+    pub fn library_path(&self, cli_path: Option<PathBuf>) -> Option<PathBuf> {
+        cli_path.or_else(|| self.library.clone())
+    }
+
+    /// Extensions to scan, lowercase and without a leading dot.
+    pub fn extensions(&self) -> Vec<String> {
+        match &self.extensions {
+            Some(extensions) => extensions
+                .iter()
+                .map(|ext| ext.trim().trim_start_matches('.').to_lowercase())
+                .filter(|ext| !ext.is_empty())
+                .collect(),
+            None => ALLOWED_EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// First value set among `layers`, in order of precedence, or `None` from the defaults.
+fn resolve<T>(layers: impl IntoIterator<Item = (Option<T>, Source)>) -> Setting<Option<T>> {
+    layers
+        .into_iter()
+        .find(|(value, _)| value.is_some())
+        .map_or(
+            Setting {
+                value: None,
+                source: Source::Default,
+            },
+            |(value, source)| Setting { value, source },
+        )
+}
+
+/// Value of an environment variable, `None` when unset or invalid.
+fn env_var<T: EnvValue>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let parsed = T::parse_env(&value);
+    if parsed.is_none() {
+        warn!("Ignoring invalid ${}: {}", name, value);
+    }
+    parsed
+}
+
+trait EnvValue: Sized {
+    fn parse_env(value: &str) -> Option<Self>;
+}
+
+impl EnvValue for usize {
+    fn parse_env(value: &str) -> Option<Self> {
+        value.trim().parse().ok()
+    }
+}
+
+impl EnvValue for PathBuf {
+    fn parse_env(value: &str) -> Option<Self> {
+        (!value.is_empty()).then(|| PathBuf::from(value))
+    }
+}
+
+impl EnvValue for bool {
+    fn parse_env(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "1" | "true" | "yes" | "on" => Some(true),
+            "" | "0" | "false" | "no" | "off" => Some(false),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::CommandLine => write!(f, "command line"),
+            Source::Environment(name) => write!(f, "${}", name),
+            Source::CommandSection(command) => write!(f, "[commands.{}]", command),
+            Source::ConfigFile => write!(f, "config file"),
+            Source::Profile => write!(f, "profile"),
+            Source::Default => write!(f, "default"),
+        }
+    }
+}
+
+/// `$MUMAN_CONFIG`, or else `$XDG_CONFIG_HOME/muman/config.toml`.
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("MUMAN_CONFIG").filter(|path| !path.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("muman").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_takes_the_first_value_set() {
+        let setting = resolve([
+            (None, Source::CommandLine),
+            (Some(2), Source::ConfigFile),
+            (Some(3), Source::Profile),
+        ]);
+        assert_eq!(setting.value, Some(2));
+        assert_eq!(setting.source, Source::ConfigFile);
+
+        let setting = resolve::<usize>([(None, Source::CommandLine)]);
+        assert_eq!(setting.value, None);
+        assert_eq!(setting.source, Source::Default);
+    }
+
+    #[test]
+    fn settings_rank_command_line_then_section_then_config() {
+        let config: Config = toml::from_str(
+            "io_jobs = 2\nextensions = [\"flac\"]\n[commands.scan]\nio_jobs = 3\ntrash = true\n",
+        )
+        .unwrap();
+        let profile = Profile {
+            library: None,
+            extensions: Some(vec!["mp3".to_string()]),
+        };
+
+        let scan = config.settings(Some("scan"), &CommandOptions::default(), &profile, false);
+        assert_eq!(scan.io_jobs.value, Some(3));
+        assert_eq!(
+            scan.io_jobs.source,
+            Source::CommandSection("scan".to_string())
+        );
+        assert_eq!(scan.extensions.value, Some(vec!["mp3".to_string()]));
+        assert_eq!(scan.extensions.source, Source::Profile);
+        assert_eq!(scan.deletion.value, Some(Deletion::Trash));
+
+        let backup = config.settings(Some("backup"), &CommandOptions::default(), &profile, false);
+        assert_eq!(backup.io_jobs.value, Some(2));
+        assert_eq!(backup.io_jobs.source, Source::ConfigFile);
+        assert_eq!(backup.deletion.value, None);
+
+        let cli = CommandOptions {
+            io_jobs: Some(5),
+            quarantine: Some(PathBuf::from("/q")),
+            ..Default::default()
+        };
+        let scan = config.settings(Some("scan"), &cli, &profile, false);
+        assert_eq!(scan.io_jobs.value, Some(5));
+        assert_eq!(scan.io_jobs.source, Source::CommandLine);
+        assert_eq!(
+            scan.deletion.value,
+            Some(Deletion::Quarantine(PathBuf::from("/q")))
+        );
+    }
+}
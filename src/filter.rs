@@ -0,0 +1,321 @@
+use std::{
+    cmp::Ordering,
+    iter::Peekable,
+    str::{Chars, FromStr},
+};
+
+use crate::track::DirtyTrack;
+
+/// A `--where` expression selecting tracks, such as `genre = jazz and (year < 1970 or label)`.
+///
+/// Comparisons are `field OP value` with `=`, `!=`, `~` (contains), `<`, `<=`, `>` and `>=`.
+/// Values are compared as numbers when both sides are numbers, otherwise as case-insensitive
+/// text, and can be quoted to hold spaces. A bare field matches tracks where it is set. They
+/// combine with `and`, `or`, `not` and parentheses.
+///
+/// Fields are title, artist, album, albumartist, genre, composer, label, isrc, isrccountry (from
+/// the ISRC), country (the release country), date, year, track, disc, duration (seconds), bitrate,
+/// samplerate, bitdepth, path, compilation, lyrics, cover, or the name of any other tag field
+/// such as MOOD.
+#[derive(Clone, Debug)]
+pub struct Filter(Expr);
+
+#[derive(Clone, Debug)]
+enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// Whether the field is set.
+    Has(String),
+    Compare(String, Op, String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Contains,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ne => "!=",
+            Op::Contains => "~",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Op(Op),
+    Word(String),
+    /// A quoted value, never taken for a keyword.
+    Quoted(String),
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, String> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            position: 0,
+        };
+        let expr = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(Filter(expr)),
+            Some(token) => Err(format!("unexpected {}", describe(token))),
+        }
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, track: &DirtyTrack) -> bool {
+        self.0.matches(track)
+    }
+}
+
+impl Expr {
+    fn matches(&self, track: &DirtyTrack) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(track) && right.matches(track),
+            Expr::Or(left, right) => left.matches(track) || right.matches(track),
+            Expr::Not(expr) => !expr.matches(track),
+            Expr::Has(field) => field_value(track, field).is_some_and(|v| !v.is_empty()),
+            Expr::Compare(field, op, expected) => {
+                // A missing field differs from every value.
+                let Some(actual) = field_value(track, field) else {
+                    return *op == Op::Ne;
+                };
+                // Fields with several values match when one of them does, and differ when all
+                // of them do.
+                let mut values = actual.split("; ");
+                if *op == Op::Ne {
+                    values.all(|value| !compare(value, Op::Eq, expected))
+                } else {
+                    values.any(|value| compare(value, *op, expected))
+                }
+            }
+        }
+    }
+}
+
+fn compare(actual: &str, op: Op, expected: &str) -> bool {
+    let ordering = match (actual.trim().parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(actual), Ok(expected)) if op != Op::Contains => actual.partial_cmp(&expected),
+        _ => {
+            let (actual, expected) = (actual.to_lowercase(), expected.to_lowercase());
+            if op == Op::Contains {
+                return actual.contains(&expected);
+            }
+            Some(actual.cmp(&expected))
+        }
+    };
+    let Some(ordering) = ordering else {
+        return false;
+    };
+    match op {
+        Op::Eq => ordering == Ordering::Equal,
+        Op::Ne => ordering != Ordering::Equal,
+        Op::Lt => ordering == Ordering::Less,
+        Op::Le => ordering != Ordering::Greater,
+        Op::Gt => ordering == Ordering::Greater,
+        Op::Ge => ordering != Ordering::Less,
+        Op::Contains => unreachable!("handled with the text comparison"),
+    }
+}
+
+/// Value of a field as text, `None` when it is not set.
+fn field_value(track: &DirtyTrack, field: &str) -> Option<String> {
+    let number = |n: Option<u32>| n.map(|n| n.to_string());
+    let flag = |set: bool| set.then(|| "yes".to_string());
+    match field {
+        "title" => track.title.clone(),
+        "artist" => track.artist.clone(),
+        "album" => track.album.clone(),
+        "albumartist" | "album_artist" => track.album_artist.clone(),
+        "genre" => track.genre.clone(),
+        "composer" => track.composer.clone(),
+        "label" => track.label.clone(),
+        "isrc" => track.isrc.clone(),
+        "isrccountry" | "isrc_country" => track.isrc_country(),
+        "country" | "releasecountry" => track.release_country(),
+        "date" => track.date.clone(),
+        "year" => number(track.year),
+        "track" => number(track.track_number),
+        "disc" => number(track.disc_number),
+        "duration" => number(track.duration),
+        "bitrate" => number(track.bitrate),
+        "samplerate" | "sample_rate" => number(track.sample_rate),
+        "bitdepth" | "bit_depth" => number(track.bit_depth.map(u32::from)),
+        "path" => track.file_path.as_ref().map(|p| p.display().to_string()),
+        "compilation" => flag(track.compilation),
+        "lyrics" => flag(track.embedded_lyrics),
+        "cover" => flag(track.embedded_cover),
+        _ => track.extra_field(field).map(str::to_string),
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' => {
+                chars.next();
+                Token::Open
+            }
+            ')' => {
+                chars.next();
+                Token::Close
+            }
+            '"' | '\'' => {
+                chars.next();
+                Token::Quoted(quoted(&mut chars, c)?)
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let equals = chars.next_if_eq(&'=').is_some();
+                Token::Op(match (c, equals) {
+                    ('=', _) => Op::Eq,
+                    ('!', true) => Op::Ne,
+                    ('<', false) => Op::Lt,
+                    ('<', true) => Op::Le,
+                    ('>', false) => Op::Gt,
+                    ('>', true) => Op::Ge,
+                    ('~', false) => Op::Contains,
+                    _ => return Err(format!("unknown operator \"{}\"", c)),
+                })
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) =
+                    chars.next_if(|c| !c.is_whitespace() && !"()=!<>~\"'".contains(*c))
+                {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Read a quoted value up to the closing `quote`, with backslash escapes.
+fn quoted(chars: &mut Peekable<Chars>, quote: char) -> Result<String, String> {
+    let mut value = String::new();
+    loop {
+        match chars.next() {
+            Some(c) if c == quote => return Ok(value),
+            Some('\\') => value.extend(chars.next()),
+            Some(c) => value.push(c),
+            None => return Err(format!("missing closing {}", quote)),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Open => "\"(\"".to_string(),
+        Token::Close => "\")\"".to_string(),
+        Token::Op(op) => format!("\"{}\"", op.symbol()),
+        Token::Word(word) | Token::Quoted(word) => format!("\"{}\"", word),
+    }
+}
+
+/// Recursive descent parser, `not` binding tighter than `and`, and `and` tighter than `or`.
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("missing closing parenthesis".to_string()),
+                }
+            }
+            Some(Token::Word(field)) => {
+                let field = field.to_lowercase();
+                if ["and", "or", "not"].contains(&field.as_str()) {
+                    return Err(format!("expected a field, got \"{}\"", field));
+                }
+                let Some(Token::Op(op)) = self.tokens.get(self.position) else {
+                    return Ok(Expr::Has(field));
+                };
+                let op = *op;
+                self.position += 1;
+                match self.next() {
+                    Some(Token::Word(value) | Token::Quoted(value)) => {
+                        Ok(Expr::Compare(field, op, value))
+                    }
+                    _ => Err(format!("expected a value after \"{}\"", field)),
+                }
+            }
+            Some(token) => Err(format!("unexpected {}", describe(&token))),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    /// Consume the unquoted keyword if it comes next.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = matches!(
+            self.tokens.get(self.position),
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword)
+        );
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+}
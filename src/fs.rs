@@ -1,6 +1,55 @@
-use std::{fs, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    hash::Hasher,
+    io::{BufWriter, IntoInnerError, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    sync::{Condvar, Mutex},
+    time::UNIX_EPOCH,
+};
 
-use log::debug;
+use log::{debug, info};
+
+use crate::{cancelled, track::Unsupported};
+
+/// Files read at the same time by default, see `--io-jobs`.
+pub const DEFAULT_IO_JOBS: usize = 8;
+
+/// Counting semaphore bounding how many files parallel reads keep open, independently of the
+/// number of threads. Network shares cope badly with many simultaneous opens.
+struct IoLimit {
+    available: Mutex<usize>,
+    released: Condvar,
+}
+
+static IO_LIMIT: IoLimit = IoLimit {
+    available: Mutex::new(DEFAULT_IO_JOBS),
+    released: Condvar::new(),
+};
+
+/// Set how many files may be read at the same time. Call before any read starts.
+pub fn set_io_jobs(jobs: usize) {
+    *IO_LIMIT.available.lock().unwrap_or_else(|e| e.into_inner()) = jobs.max(1);
+}
+
+/// Run `read` once one of the I/O slots is free.
+pub fn with_io_slot<T>(read: impl FnOnce() -> T) -> T {
+    let mut available = IO_LIMIT.available.lock().unwrap_or_else(|e| e.into_inner());
+    while *available == 0 {
+        available = IO_LIMIT
+            .released
+            .wait(available)
+            .unwrap_or_else(|e| e.into_inner());
+    }
+    *available -= 1;
+    drop(available);
+
+    let result = read();
+
+    *IO_LIMIT.available.lock().unwrap_or_else(|e| e.into_inner()) += 1;
+    IO_LIMIT.released.notify_one();
+    result
+}
 
 /// Recursively traverse a directory and collect file paths. Optionally filter files and changes
 /// the initial capacity of the returned vector.
@@ -11,23 +60,24 @@ pub fn recurse_directory(
     file_count: Option<usize>,
 ) -> Vec<PathBuf> {
     let mut files = Vec::with_capacity(
-        file_count.unwrap_or(fs::read_dir(&path).map(|rd| rd.count()).unwrap_or(0)),
+        file_count.unwrap_or(fs::read_dir(path).map(|rd| rd.count()).unwrap_or(0)),
     );
 
     let mut dirs_to_visit = Vec::with_capacity(16);
     dirs_to_visit.push(path.clone());
 
     while let Some(current_dir) = dirs_to_visit.pop() {
+        if cancelled() {
+            break;
+        }
         if let Ok(entries) = std::fs::read_dir(&current_dir) {
             for entry in entries.flatten() {
                 let path = entry.path();
 
                 if path.is_dir() && recursive {
                     dirs_to_visit.push(path);
-                } else if path.is_file() {
-                    if filter.map_or(true, |f| f(&path)) {
-                        files.push(path);
-                    }
+                } else if path.is_file() && filter.is_none_or(|f| f(&path)) {
+                    files.push(path);
                 }
             }
         }
@@ -36,44 +86,529 @@ pub fn recurse_directory(
     files
 }
 
+/// Buffer of `write_file`, large so that network shares get a few big writes rather than one per
+/// line.
+const WRITE_BUFFER: usize = 1 << 20;
+
+/// Create the file at `path` and fill it with `write` through a large buffer, then flush and sync
+/// it. Errors of the last writes, which a dropped writer would swallow, are returned too.
+pub fn write_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<fs::File>) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::with_capacity(WRITE_BUFFER, fs::File::create(path)?);
+    write(&mut out)?;
+    out.into_inner()
+        .map_err(IntoInnerError::into_error)?
+        .sync_all()
+}
+
+/// Move a file, copying it when `from` and `to` are on different filesystems.
+pub fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => {
+            fs::copy(from, to)?;
+            fs::remove_file(from)
+        }
+        result => result,
+    }
+}
+
+/// What deleting a file does, see `--trash` and `--quarantine`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Deletion {
+    /// Unlink the file.
+    Remove,
+    /// Move the file to the freedesktop.org trash of the user, where file managers can restore it.
+    Trash,
+    /// Move the file under this folder, in a folder named after the day and at its absolute path,
+    /// e.g. "2024-05-01/home/me/Music/Song.lrc".
+    Quarantine(PathBuf),
+}
+
+static DELETION: Mutex<Deletion> = Mutex::new(Deletion::Remove);
+
+/// Set what `delete_file` does, at the start of each run.
+pub fn set_deletion(deletion: Deletion) {
+    *DELETION.lock().unwrap_or_else(|e| e.into_inner()) = deletion;
+}
+
+/// Delete a file the way `set_deletion` asked. Returns where the file was moved to, `None` when it
+/// was removed.
+pub fn delete_file(path: &Path) -> std::io::Result<Option<PathBuf>> {
+    let deletion = DELETION.lock().unwrap_or_else(|e| e.into_inner()).clone();
+    match &deletion {
+        Deletion::Remove => fs::remove_file(path).map(|()| None),
+        Deletion::Trash => trash_file(path).map(Some),
+        Deletion::Quarantine(folder) => {
+            let (year, month, day, ..) = civil_from_unix(unix_now());
+            let absolute = std::path::absolute(path)?;
+            let relative: PathBuf = absolute
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_)))
+                .collect();
+            let target = free_path(
+                &folder
+                    .join(format!("{}-{:02}-{:02}", year, month, day))
+                    .join(relative),
+            );
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            move_file(path, &target).map(|()| Some(target))
+        }
+    }
+}
+
+/// Move a file to the home trash, `$XDG_DATA_HOME/Trash`, with the ".trashinfo" file telling
+/// where it came from. Files of other filesystems are copied there.
+fn trash_file(path: &Path) -> std::io::Result<PathBuf> {
+    let trash = std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share"))
+        })
+        .ok_or_else(|| std::io::Error::other("no trash folder: $HOME is not set"))?
+        .join("Trash");
+    fs::create_dir_all(trash.join("files"))?;
+    fs::create_dir_all(trash.join("info"))?;
+
+    let absolute = std::path::absolute(path)?;
+    let name = path
+        .file_name()
+        .ok_or_else(|| std::io::Error::other("not a file"))?
+        .to_string_lossy()
+        .into_owned();
+    let now = unix_now();
+    let (year, month, day, hour, minute) = civil_from_unix(now);
+    let info = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}-{:02}-{:02}T{:02}:{:02}:{:02}\n",
+        percent_encode(&absolute.to_string_lossy()),
+        year,
+        month,
+        day,
+        hour,
+        minute,
+        now % 60
+    );
+    // Creating the info file first reserves the name, as the specification asks
+    for n in 1.. {
+        let trashed = if n == 1 {
+            name.clone()
+        } else {
+            format!("{}.{}", name, n)
+        };
+        let info_path = trash.join("info").join(format!("{}.trashinfo", trashed));
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut file) => {
+                file.write_all(info.as_bytes())?;
+                let target = trash.join("files").join(&trashed);
+                return match move_file(path, &target) {
+                    Ok(()) => Ok(target),
+                    Err(e) => {
+                        let _ = fs::remove_file(&info_path);
+                        Err(e)
+                    }
+                };
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    unreachable!()
+}
+
+/// Put back at `path` a file `delete_file` moved to `kept`, removing its ".trashinfo" file when it
+/// was in the trash.
+pub fn restore_file(kept: &Path, path: &Path) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    move_file(kept, path)?;
+    if let (Some(files), Some(name)) = (kept.parent(), kept.file_name())
+        && files.file_name().is_some_and(|n| n == "files")
+        && let Some(trash) = files.parent()
+        && trash.file_name().is_some_and(|n| n == "Trash")
+    {
+        let mut info = name.to_os_string();
+        info.push(".trashinfo");
+        let _ = fs::remove_file(trash.join("info").join(info));
+    }
+    Ok(())
+}
+
+/// `path`, or else the first of "name.2.ext", "name.3.ext"... that does not exist.
+fn free_path(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|n| match n {
+            1 => path.to_path_buf(),
+            n => path.with_file_name(format!("{}.{}{}", stem, n, extension)),
+        })
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Escape a path for a ".trashinfo" file, keeping "/" and unreserved characters.
+fn percent_encode(path: &str) -> String {
+    let mut encoded = String::new();
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-_.~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+impl fmt::Display for Deletion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Deletion::Remove => write!(f, "remove"),
+            Deletion::Trash => write!(f, "trash"),
+            Deletion::Quarantine(folder) => write!(f, "quarantine in {}", folder.display()),
+        }
+    }
+}
+
+/// Count the files under `path`, and those matching `filter`, stopping after `limit` files.
+pub fn count_files(path: &Path, filter: &dyn Fn(&Path) -> bool, limit: usize) -> (usize, usize) {
+    let (mut total, mut matching) = (0, 0);
+    let mut dirs_to_visit = vec![path.to_path_buf()];
+    while let Some(current_dir) = dirs_to_visit.pop() {
+        let Ok(entries) = fs::read_dir(&current_dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs_to_visit.push(path);
+            } else if path.is_file() {
+                total += 1;
+                if filter(&path) {
+                    matching += 1;
+                }
+                if total >= limit {
+                    return (total, matching);
+                }
+            }
+        }
+    }
+    (total, matching)
+}
+
+/// Read a list of file paths from stdin, NUL separated if any NUL byte is present (`find -print0`,
+/// `fd -0`) and newline separated otherwise.
+pub fn read_paths_from_stdin() -> std::io::Result<Vec<PathBuf>> {
+    let mut input = Vec::new();
+    std::io::stdin().read_to_end(&mut input)?;
+    let separator = if input.contains(&0) { b'\0' } else { b'\n' };
+
+    Ok(input
+        .split(|byte| *byte == separator)
+        .map(|line| String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line)).into_owned())
+        .filter(|line| !line.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Number of bytes read from each end of a file when computing its quick hash.
+const QUICK_HASH_CHUNK: u64 = 64 * 1024;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is stable across Rust releases, so it can be
+/// persisted in the cache.
+pub struct Fnv64(u64);
+
+impl Default for Fnv64 {
+    fn default() -> Self {
+        Fnv64(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for Fnv64 {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+}
+
+/// Hash the size plus the first and last chunks of a file. Cheap enough to run on every scan while
+/// still catching rewrites that keep the size and mtime intact.
+pub fn quick_hash(path: &Path) -> std::io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut hasher = Fnv64::default();
+    hasher.write_u64(size);
+
+    let mut buffer = Vec::with_capacity(QUICK_HASH_CHUNK as usize);
+    (&mut file)
+        .take(QUICK_HASH_CHUNK)
+        .read_to_end(&mut buffer)?;
+    hasher.write(&buffer);
+
+    if size > QUICK_HASH_CHUNK * 2 {
+        buffer.clear();
+        file.seek(SeekFrom::End(-(QUICK_HASH_CHUNK as i64)))?;
+        file.read_to_end(&mut buffer)?;
+        hasher.write(&buffer);
+    }
+
+    Ok(hasher.finish())
+}
+
+/// Hash of the whole content of a file, slow but catching any change.
+pub fn full_hash(path: &Path) -> std::io::Result<u64> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Fnv64::default();
+    let mut buffer = vec![0; 1 << 16];
+    loop {
+        if cancelled() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Interrupted,
+                "cancelled",
+            ));
+        }
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.write(&buffer[..read]);
+    }
+}
+
+/// What a file looked like the last time it was scanned.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileEntry {
+    pub size: u64,
+    /// Seconds since the unix epoch.
+    pub mtime: u64,
+    pub quick_hash: u64,
+    /// Hash of the tag values read from the file, see `DirtyTrack::tag_hash`.
+    pub tag_hash: u64,
+    /// When a scan first found the file, seconds since the unix epoch.
+    pub first_seen: u64,
+}
+
+impl FileEntry {
+    pub fn new(path: &Path, tag_hash: u64) -> std::io::Result<Self> {
+        let (size, mtime) = stat(path)?;
+        Ok(FileEntry {
+            size,
+            mtime,
+            quick_hash: quick_hash(path)?,
+            tag_hash,
+            first_seen: unix_now(),
+        })
+    }
+
+    /// Whether the file differs from this entry. Unreadable files count as changed. A changed
+    /// mtime alone is not enough, the quick hash has to differ as well.
+    fn is_outdated(&self, path: &Path) -> bool {
+        let Ok((size, mtime)) = stat(path) else {
+            return true;
+        };
+
+        if size != self.size {
+            return true;
+        }
+        if mtime == self.mtime {
+            return false;
+        }
+        quick_hash(path).map_or(true, |hash| hash != self.quick_hash)
+    }
+}
+
+/// Size and mtime of a file, the mtime in seconds since the unix epoch.
+pub fn stat(path: &Path) -> std::io::Result<(u64, u64)> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    Ok((metadata.len(), mtime))
+}
+
 const CACHE_PATH: &str = "cache.txt";
+/// Format of the cache file, written on its first line. Version 2 added `first_seen` to entries.
+const CACHE_VERSION: u32 = 2;
+const CACHE_LOCK_PATH: &str = "cache.txt.lock";
 
+/// Exclusive lock on the cache, to hold from reading it to writing it back so that concurrent runs
+/// do not overwrite each other's updates. Released when dropped.
+pub struct CacheLock {
+    _file: fs::File,
+}
+
+#[derive(Default)]
 pub struct Cache {
     pub last_scan: Option<u32>,
     pub scan_count: Option<usize>,
+    pub files: HashMap<PathBuf, FileEntry>,
+    /// Files that could not be read at all, skipped by scans until they change.
+    pub unsupported: HashMap<PathBuf, (FileEntry, Unsupported)>,
+    /// Tracks found moved whose sidecars and playlist entries were not updated yet, see
+    /// `scan --follow-moves`.
+    pub moved: Vec<(PathBuf, PathBuf)>,
 }
 
 impl Cache {
+    /// Take the cache lock, waiting for other runs holding it.
+    pub fn lock() -> std::io::Result<CacheLock> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(CACHE_LOCK_PATH)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(fs::TryLockError::WouldBlock) => {
+                info!("Waiting for another run to release the cache");
+                file.lock()?;
+            }
+            Err(fs::TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(CacheLock { _file: file })
+    }
+
     pub fn new() -> Self {
-        Self::read_from_file().unwrap_or(Cache {
-            last_scan: None,
-            scan_count: None,
-        })
+        Self::read_from_file().unwrap_or_default()
+    }
+
+    /// Whether the file differs from its cached entry. Unknown and unreadable files count as
+    /// changed. A changed mtime alone is not enough, the quick hash has to differ as well.
+    pub fn has_changed(&self, path: &Path) -> bool {
+        self.files
+            .get(path)
+            .is_none_or(|entry| entry.is_outdated(path))
+    }
+
+    /// Why the file was found unsupported by a previous scan, if it was and has not changed since.
+    pub fn unsupported(&self, path: &Path) -> Option<Unsupported> {
+        let (entry, reason) = self.unsupported.get(path)?;
+        (!entry.is_outdated(path)).then_some(*reason)
+    }
+
+    /// Store the entry of a file, keeping the first seen date of a previous entry.
+    pub fn record(&mut self, path: PathBuf, mut entry: FileEntry) {
+        if let Some(previous) = self.files.get(&path) {
+            entry.first_seen = previous.first_seen;
+        }
+        self.files.insert(path, entry);
+    }
+
+    /// Remember moves until their sidecars and playlists are updated. A track moved again since
+    /// keeps its first path, so what still refers to it there is found.
+    pub fn record_moves(&mut self, moves: &[(PathBuf, PathBuf)]) {
+        for (from, to) in moves {
+            match self.moved.iter_mut().find(|(_, pending)| pending == from) {
+                Some((_, pending)) => *pending = to.clone(),
+                None => self.moved.push((from.clone(), to.clone())),
+            }
+        }
+        self.moved.retain(|(from, to)| from != to);
+    }
+
+    /// Drop the entries of files that were removed or changed since they were recorded. Returns
+    /// how many entries were dropped.
+    pub fn vacuum(&mut self) -> usize {
+        let stale: Vec<PathBuf> = self
+            .files
+            .keys()
+            .filter(|path| self.has_changed(path))
+            .cloned()
+            .collect();
+        for path in &stale {
+            debug!("Dropping stale cache entry: {}", path.display());
+            self.files.remove(path);
+        }
+        let unsupported = self.unsupported.len();
+        self.unsupported
+            .retain(|path, (entry, _)| !entry.is_outdated(path));
+        let moved = self.moved.len();
+        self.moved.retain(|(_, to)| self.files.contains_key(to));
+        stale.len() + unsupported - self.unsupported.len() + moved - self.moved.len()
+    }
+
+    /// Size of the cache file on disk, `None` if it does not exist.
+    pub fn file_size() -> Option<u64> {
+        fs::metadata(CACHE_PATH).ok().map(|m| m.len())
+    }
+
+    pub fn clear() -> std::io::Result<()> {
+        match fs::remove_file(CACHE_PATH) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
     }
 
     pub fn write_to_file(&self) -> std::io::Result<()> {
-        let mut content = String::new();
+        let mut content = format!("version: {}\n", CACHE_VERSION);
         if let Some(last_scan) = self.last_scan {
-            content.push_str(&format!(
-                "last_scan: {}:{} {}/{}/{}\n",
-                (last_scan / 100) % 100,
-                last_scan % 100,
-                (last_scan / 10000) % 100,
-                (last_scan / 1000000) % 100,
-                last_scan / 100000000
-            ));
+            content.push_str(&format!("last_scan: {}\n", format_datetime_u32(last_scan)));
         }
         if let Some(scan_count) = self.scan_count {
             content.push_str(&format!("scan_count: {}\n", scan_count));
         }
-        fs::write(CACHE_PATH, content)
+        for (path, entry) in &self.files {
+            content.push_str(&format!("file: {}\n", format_file_entry(path, entry)));
+        }
+        for (path, (entry, reason)) in &self.unsupported {
+            content.push_str(&format!(
+                "unsupported: {} {}\n",
+                reason.name(),
+                format_file_entry(path, entry)
+            ));
+        }
+        for (from, to) in &self.moved {
+            content.push_str(&format!("moved: {}\t{}\n", from.display(), to.display()));
+        }
+
+        // Write a temporary file and move it in place, so readers never see a partial cache.
+        let temporary = format!("{}.{}.tmp", CACHE_PATH, std::process::id());
+        let result = write_file(Path::new(&temporary), |out| {
+            out.write_all(content.as_bytes())
+        })
+        .and_then(|_| fs::rename(&temporary, CACHE_PATH));
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        result
     }
 
+    /// Read the cache file. Damaged content, such as a file cut short by a crash, is skipped line
+    /// by line rather than failing the whole read.
     pub fn read_from_file() -> std::io::Result<Self> {
-        let content = fs::read_to_string(CACHE_PATH)?;
-        let mut cache = Cache::new();
+        let bytes = fs::read(CACHE_PATH)?;
+        let content = String::from_utf8_lossy(&bytes);
+        let mut cache = Cache::default();
+        // Caches without a version line predate `first_seen`
+        let mut version = 1;
+
+        // A last line without its newline may have been cut, e.g. in the middle of a path.
+        let complete = match content.rfind('\n') {
+            Some(end) => &content[..=end],
+            None => "",
+        };
+        if complete.len() < content.len() {
+            debug!("Ignoring incomplete last cache line");
+        }
 
-        for line in content.lines() {
+        for line in complete.lines() {
             let parts: Vec<&str> = line.splitn(2, ':').collect();
             if parts.len() != 2 {
                 debug!("Invalid cache line: {}", line);
@@ -83,6 +618,7 @@ impl Cache {
             let value = parts[1].trim();
 
             match key {
+                "version" => version = value.parse().unwrap_or(CACHE_VERSION),
                 "last_scan" => {
                     debug!("Parsing last_scan: {}", value);
                     if let Some(timestamp) = parse_datetime_to_u32(value) {
@@ -95,6 +631,27 @@ impl Cache {
                         cache.scan_count = Some(count);
                     }
                 }
+                "file" => match parse_file_entry(value, version) {
+                    Some((path, entry)) => {
+                        cache.files.insert(path, entry);
+                    }
+                    None => debug!("Invalid file entry: {}", value),
+                },
+                "unsupported" => match value.split_once(' ').and_then(|(reason, entry)| {
+                    Some((
+                        Unsupported::from_name(reason)?,
+                        parse_file_entry(entry, version)?,
+                    ))
+                }) {
+                    Some((reason, (path, entry))) => {
+                        cache.unsupported.insert(path, (entry, reason));
+                    }
+                    None => debug!("Invalid unsupported file entry: {}", value),
+                },
+                "moved" => match value.split_once('\t') {
+                    Some((from, to)) => cache.moved.push((from.into(), to.into())),
+                    None => debug!("Invalid moved entry: {}", value),
+                },
                 _ => {}
             }
         }
@@ -103,6 +660,104 @@ impl Cache {
     }
 }
 
+/// Cache line of a file, read back by `parse_file_entry`.
+fn format_file_entry(path: &Path, entry: &FileEntry) -> String {
+    format!(
+        "{} {} {:016x} {:016x} {} {}",
+        entry.size,
+        entry.mtime,
+        entry.quick_hash,
+        entry.tag_hash,
+        entry.first_seen,
+        path.display()
+    )
+}
+
+/// Parse `<size> <mtime> <quick_hash> <tag_hash> <first_seen> <path>`, the path goes last since it
+/// may contain spaces. Version 1 caches have no `first_seen`, the mtime takes its place.
+fn parse_file_entry(value: &str, version: u32) -> Option<(PathBuf, FileEntry)> {
+    let has_first_seen = version >= 2;
+    let fields = if has_first_seen { 6 } else { 5 };
+    let parts: Vec<&str> = value.splitn(fields, ' ').collect();
+    if parts.len() != fields {
+        return None;
+    }
+
+    let mtime = parts[1].parse().ok()?;
+    let first_seen = if has_first_seen {
+        parts[4].parse().ok()?
+    } else {
+        mtime
+    };
+    let path = parts[fields - 1];
+    let entry = FileEntry {
+        size: parts[0].parse().ok()?,
+        mtime,
+        quick_hash: u64::from_str_radix(parts[2], 16).ok()?,
+        tag_hash: u64::from_str_radix(parts[3], 16).ok()?,
+        first_seen,
+    };
+    Some((PathBuf::from(path), entry))
+}
+
+/// File in muman's state folder, `$XDG_STATE_HOME/muman` or `~/.local/state/muman`.
+pub fn state_path(name: &str) -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("state"))
+        })
+        .map(|dir| dir.join("muman").join(name))
+}
+
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// UTC (year, month, day, hour, minute) of a unix timestamp.
+pub fn civil_from_unix(secs: u64) -> (u32, u32, u32, u32, u32) {
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // See http://howardhinnant.github.io/date_algorithms.html
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = (yoe + era * 400 + i64::from(month <= 2)) as u32;
+
+    (
+        year,
+        month,
+        day,
+        (rem / 3600) as u32,
+        ((rem % 3600) / 60) as u32,
+    )
+}
+
+/// Current UTC time in the YYMMDDHHmm form used by the cache.
+pub fn now_to_u32() -> u32 {
+    let (year, month, day, hour, minute) = civil_from_unix(unix_now());
+    (year % 100) * 100000000 + month * 1000000 + day * 10000 + hour * 100 + minute
+}
+
+/// Format a YYMMDDHHmm value as `HH:mm DD/MM/YY`, the inverse of `parse_datetime_to_u32`.
+pub fn format_datetime_u32(datetime: u32) -> String {
+    format!(
+        "{:02}:{:02} {:02}/{:02}/{:02}",
+        (datetime / 100) % 100,
+        datetime % 100,
+        (datetime / 10000) % 100,
+        (datetime / 1000000) % 100,
+        datetime / 100000000
+    )
+}
+
 fn parse_datetime_to_u32(datetime: &str) -> Option<u32> {
     let datetime_parts: Vec<&str> = datetime.split_whitespace().collect();
     if datetime_parts.len() != 2 {
@@ -130,3 +785,41 @@ fn parse_datetime_to_u32(datetime: &str) -> Option<u32> {
     // In YYMMDDHHmm
     Some(year * 100000000 + month * 1000000 + day * 10000 + hour * 100 + minute)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_file_entry_reads_version_1_entries() {
+        let (path, entry) = parse_file_entry(
+            "4129 100 00000000000000ff 0000000000000001 2024 x/y.flac",
+            1,
+        )
+        .unwrap();
+        assert_eq!(path, Path::new("2024 x/y.flac"));
+        assert_eq!(entry.size, 4129);
+        assert_eq!(entry.quick_hash, 0xff);
+        assert_eq!(entry.first_seen, 100);
+    }
+
+    #[test]
+    fn parse_file_entry_reads_version_2_entries() {
+        let entry = FileEntry {
+            size: 4129,
+            mtime: 100,
+            quick_hash: 0xff,
+            tag_hash: 1,
+            first_seen: 50,
+        };
+        let line = format_file_entry(Path::new("2024 x/y.flac"), &entry);
+        assert_eq!(
+            parse_file_entry(&line, CACHE_VERSION),
+            Some((PathBuf::from("2024 x/y.flac"), entry))
+        );
+        assert_eq!(
+            parse_file_entry("4129 100 ff 1 x.flac", CACHE_VERSION),
+            None
+        );
+    }
+}
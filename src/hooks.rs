@@ -0,0 +1,273 @@
+use std::{path::Path, process::Command};
+
+use log::{debug, warn};
+use serde::Deserialize;
+use serde_json::json;
+use ureq::Agent;
+
+use crate::{Status, summary::Summary};
+
+/// Event names as used in the `[hooks]` section of the config.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    TrackAdded,
+    TrackChanged,
+    TrackMoved,
+    FileDeleted,
+    CommandFinished,
+    ProblemsFound,
+}
+
+impl EventKind {
+    fn name(self) -> &'static str {
+        match self {
+            EventKind::TrackAdded => "track-added",
+            EventKind::TrackChanged => "track-changed",
+            EventKind::TrackMoved => "track-moved",
+            EventKind::FileDeleted => "file-deleted",
+            EventKind::CommandFinished => "command-finished",
+            EventKind::ProblemsFound => "problems-found",
+        }
+    }
+}
+
+pub enum Event<'a> {
+    /// A file was seen for the first time by a scan.
+    TrackAdded(&'a Path),
+    /// A known file changed since the previous scan.
+    TrackChanged(&'a Path),
+    /// A known file was found at a new path, with the same content.
+    TrackMoved { from: &'a Path, to: &'a Path },
+    /// A command deleted a file, such as a stray copy or a duplicate sidecar. `kept` is where it
+    /// was moved to with the trash or quarantine deletion settings.
+    FileDeleted {
+        path: &'a Path,
+        kept: Option<&'a Path>,
+    },
+    CommandFinished {
+        summary: &'a Summary,
+        status: Status,
+    },
+    /// A command finished with errors, such as unreadable files.
+    ProblemsFound {
+        summary: &'a Summary,
+        status: Status,
+    },
+}
+
+impl Event<'_> {
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Event::TrackAdded(_) => EventKind::TrackAdded,
+            Event::TrackChanged(_) => EventKind::TrackChanged,
+            Event::TrackMoved { .. } => EventKind::TrackMoved,
+            Event::FileDeleted { .. } => EventKind::FileDeleted,
+            Event::CommandFinished { .. } => EventKind::CommandFinished,
+            Event::ProblemsFound { .. } => EventKind::ProblemsFound,
+        }
+    }
+
+    /// Event details as `MUMAN_*` environment variables.
+    fn env(&self) -> Vec<(&'static str, String)> {
+        let mut env = vec![("MUMAN_EVENT", self.kind().name().to_string())];
+        match self {
+            Event::TrackAdded(path) | Event::TrackChanged(path) => {
+                env.push(("MUMAN_PATH", path.display().to_string()))
+            }
+            Event::TrackMoved { from, to } => {
+                env.push(("MUMAN_PATH", to.display().to_string()));
+                env.push(("MUMAN_PREVIOUS_PATH", from.display().to_string()));
+            }
+            Event::FileDeleted { path, kept } => {
+                env.push(("MUMAN_PATH", path.display().to_string()));
+                if let Some(kept) = kept {
+                    env.push(("MUMAN_KEPT_PATH", kept.display().to_string()));
+                }
+            }
+            Event::CommandFinished { summary, status }
+            | Event::ProblemsFound { summary, status } => {
+                env.push(("MUMAN_COMMAND", summary.command.clone()));
+                env.push(("MUMAN_STATUS", (*status as u8).to_string()));
+                env.push(("MUMAN_ERRORS", summary.errors.to_string()));
+            }
+        }
+        env
+    }
+
+    /// One line description, for notifications.
+    fn message(&self) -> String {
+        match self {
+            Event::TrackAdded(path) => format!("New track: {}", path.display()),
+            Event::TrackChanged(path) => format!("Track changed: {}", path.display()),
+            Event::TrackMoved { from, to } => {
+                format!("Track moved: {} -> {}", from.display(), to.display())
+            }
+            Event::FileDeleted { path, kept: None } => format!("File deleted: {}", path.display()),
+            Event::FileDeleted {
+                path,
+                kept: Some(kept),
+            } => format!(
+                "File deleted: {}, kept at {}",
+                path.display(),
+                kept.display()
+            ),
+            Event::CommandFinished { summary, status } => format!(
+                "muman {} finished with status {}: matched {}, modified {}, errors {}",
+                summary.command, *status as u8, summary.matched, summary.modified, summary.errors
+            ),
+            Event::ProblemsFound { summary, .. } => format!(
+                "muman {} ran into {} errors, see its log",
+                summary.command, summary.errors
+            ),
+        }
+    }
+}
+
+/// Something reacting to lifecycle events, such as shell commands and webhooks from the config.
+pub trait Hook: Send + Sync {
+    fn handles(&self, kind: EventKind) -> bool;
+    fn fire(&self, event: &Event);
+}
+
+/// Runs a shell command with the event details in its environment.
+pub struct ShellHook {
+    pub kind: EventKind,
+    pub command: String,
+}
+
+impl Hook for ShellHook {
+    fn handles(&self, kind: EventKind) -> bool {
+        self.kind == kind
+    }
+
+    fn fire(&self, event: &Event) {
+        debug!("Running hook: {}", self.command);
+        let mut command = if cfg!(windows) {
+            let mut command = Command::new("cmd");
+            command.arg("/C");
+            command
+        } else {
+            let mut command = Command::new("sh");
+            command.arg("-c");
+            command
+        };
+        command.arg(&self.command).envs(event.env());
+
+        match command.status() {
+            Ok(status) if !status.success() => {
+                warn!("Hook `{}` exited with {}", self.command, status)
+            }
+            Err(e) => warn!("Could not run hook `{}`: {}", self.command, e),
+            Ok(_) => {}
+        }
+    }
+}
+
+/// Payload sent by a webhook.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum WebhookFormat {
+    /// The event and the run summary as a JSON object.
+    #[default]
+    Json,
+    /// A plain text message for an ntfy topic URL.
+    Ntfy,
+    /// A message for a Discord webhook URL.
+    Discord,
+}
+
+/// POSTs events to a URL, from the `[[webhooks]]` entries of the config.
+#[derive(Deserialize, Clone, Debug)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+    #[serde(default = "default_webhook_events")]
+    pub events: Vec<EventKind>,
+}
+
+fn default_webhook_events() -> Vec<EventKind> {
+    vec![EventKind::CommandFinished, EventKind::ProblemsFound]
+}
+
+/// A webhook of the config with the HTTP client it is called with, shared by the webhooks of a
+/// run so connections are reused.
+pub struct WebhookHook {
+    pub webhook: Webhook,
+    pub agent: Agent,
+}
+
+impl Hook for WebhookHook {
+    fn handles(&self, kind: EventKind) -> bool {
+        self.webhook.events.contains(&kind)
+    }
+
+    fn fire(&self, event: &Event) {
+        let url = &self.webhook.url;
+        debug!("Calling webhook: {}", url);
+        let request = self.agent.post(url);
+        let result = match self.webhook.format {
+            WebhookFormat::Json => {
+                let mut body = json!({ "event": event.kind().name(), "message": event.message() });
+                match event {
+                    Event::TrackAdded(path) | Event::TrackChanged(path) => {
+                        body["path"] = json!(path.display().to_string())
+                    }
+                    Event::TrackMoved { from, to } => {
+                        body["path"] = json!(to.display().to_string());
+                        body["previous_path"] = json!(from.display().to_string());
+                    }
+                    Event::FileDeleted { path, kept } => {
+                        body["path"] = json!(path.display().to_string());
+                        if let Some(kept) = kept {
+                            body["kept_path"] = json!(kept.display().to_string());
+                        }
+                    }
+                    Event::CommandFinished { summary, status }
+                    | Event::ProblemsFound { summary, status } => {
+                        body["status"] = json!(*status as u8);
+                        body["summary"] = json!(summary);
+                    }
+                }
+                request
+                    .header("Content-Type", "application/json")
+                    .send(body.to_string())
+            }
+            WebhookFormat::Ntfy => {
+                let priority = match event.kind() {
+                    EventKind::ProblemsFound => "high",
+                    _ => "default",
+                };
+                request
+                    .header("Title", "muman")
+                    .header("Priority", priority)
+                    .send(event.message())
+            }
+            WebhookFormat::Discord => request
+                .header("Content-Type", "application/json")
+                .send(json!({ "content": event.message() }).to_string()),
+        };
+        if let Err(e) = result {
+            warn!("Webhook {} failed: {}", url, e);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Hooks {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+impl Hooks {
+    pub fn register(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn fire(&self, event: Event) {
+        let kind = event.kind();
+        for hook in self.hooks.iter().filter(|h| h.handles(kind)) {
+            hook.fire(&event);
+        }
+    }
+}
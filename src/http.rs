@@ -0,0 +1,35 @@
+use std::time::Duration;
+
+use ureq::{Agent, Proxy};
+
+use crate::config::Config;
+
+/// Sent when `http.user_agent` is not set. MusicBrainz asks clients to identify themselves as
+/// "app/version ( contact )".
+const USER_AGENT: &str = concat!(
+    "muman/",
+    env!("CARGO_PKG_VERSION"),
+    " ( ",
+    env!("CARGO_PKG_REPOSITORY"),
+    " )"
+);
+/// Seconds before a request is given up when `http.timeout` is not set.
+const DEFAULT_TIMEOUT: u64 = 30;
+
+/// HTTP client with the proxy, timeout and User-Agent of the `[http]` section of the config.
+/// Without a proxy there, the usual `HTTPS_PROXY`/`ALL_PROXY` variables apply. Clones share their
+/// connections.
+pub fn agent(config: &Config) -> Result<Agent, String> {
+    let http = &config.http;
+    let proxy = match &http.proxy {
+        Some(url) => Some(Proxy::new(url).map_err(|e| format!("Invalid proxy {}: {}", url, e))?),
+        None => Proxy::try_from_env(),
+    };
+    let timeout = Duration::from_secs(http.timeout.unwrap_or(DEFAULT_TIMEOUT));
+    Ok(Agent::config_builder()
+        .proxy(proxy)
+        .user_agent(http.user_agent.as_deref().unwrap_or(USER_AGENT))
+        .timeout_global(Some(timeout))
+        .build()
+        .into())
+}
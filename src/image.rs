@@ -0,0 +1,94 @@
+use std::fmt;
+
+/// Covers narrower or shorter than this, in pixels, look blurry on most players.
+pub const SMALL_COVER: u32 = 500;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+}
+
+/// Format and size of an image, read from its header alone.
+#[derive(Clone, Copy, Debug)]
+pub struct ImageInfo {
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl ImageInfo {
+    /// Whether the image is narrower or shorter than `min_size` pixels.
+    pub fn is_smaller_than(&self, min_size: u32) -> bool {
+        self.width.min(self.height) < min_size
+    }
+}
+
+impl fmt::Display for ImageFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ImageFormat::Jpeg => write!(f, "JPEG"),
+            ImageFormat::Png => write!(f, "PNG"),
+        }
+    }
+}
+
+impl fmt::Display for ImageInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{} {}", self.width, self.height, self.format)
+    }
+}
+
+/// Format and size of a JPEG or PNG image, without decoding it. `None` for other formats and
+/// truncated headers.
+pub fn image_info(data: &[u8]) -> Option<ImageInfo> {
+    if data.starts_with(PNG_SIGNATURE) {
+        png_info(data)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        jpeg_info(data)
+    } else {
+        None
+    }
+}
+
+/// The IHDR chunk always comes first, right after the signature.
+fn png_info(data: &[u8]) -> Option<ImageInfo> {
+    if data.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    Some(ImageInfo {
+        format: ImageFormat::Png,
+        width: u32::from_be_bytes(data.get(16..20)?.try_into().ok()?),
+        height: u32::from_be_bytes(data.get(20..24)?.try_into().ok()?),
+    })
+}
+
+/// Walk the segments up to the first start of frame, which holds the size. Segments such as EXIF
+/// or embedded thumbnails come before it and are skipped by their length.
+fn jpeg_info(data: &[u8]) -> Option<ImageInfo> {
+    let be_u16 = |at: usize| Some(u16::from_be_bytes(data.get(at..at + 2)?.try_into().ok()?));
+    let mut position = 2;
+    loop {
+        if *data.get(position)? != 0xff {
+            return None;
+        }
+        let marker = *data.get(position + 1)?;
+        match marker {
+            // Fill byte before a marker
+            0xff => position += 1,
+            // Markers without a segment
+            0x01 | 0xd0..=0xd9 => position += 2,
+            // Start of frame, except DHT, JPG and DAC which share the range
+            0xc0..=0xcf if ![0xc4, 0xc8, 0xcc].contains(&marker) => {
+                return Some(ImageInfo {
+                    format: ImageFormat::Jpeg,
+                    height: u32::from(be_u16(position + 5)?),
+                    width: u32::from(be_u16(position + 7)?),
+                });
+            }
+            _ => position += 2 + usize::from(be_u16(position + 2)?),
+        }
+    }
+}
@@ -0,0 +1,214 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{
+        Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::fs::{quick_hash, unix_now};
+
+/// Command being run and the ID of its run, stamped on every journal entry. The ID is the start
+/// time and process ID, plus a count when a front end runs several commands in one process, so
+/// the changes of a run can be undone together.
+static COMMAND: Mutex<Option<(String, String)>> = Mutex::new(None);
+/// Commands started by this process.
+static RUNS: AtomicU32 = AtomicU32::new(0);
+
+/// Start a new run of `command`, called before each command.
+pub fn set_command(command: &str) {
+    let mut run = format!("{}-{}", unix_now(), std::process::id());
+    let count = RUNS.fetch_add(1, Ordering::Relaxed);
+    if count > 0 {
+        run.push_str(&format!("-{}", count));
+    }
+    *COMMAND.lock().unwrap_or_else(|e| e.into_inner()) = Some((command.to_string(), run));
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// One field of a tag changed, `before` and `after` hold its values.
+    TagEdit,
+    /// A file was moved, `after` holds the new path.
+    Rename,
+    /// A file was removed, `before` holds its content when it is text and `after` where it was
+    /// moved to, in the trash or a quarantine folder.
+    Delete,
+    /// A file was written, such as a lyrics sidecar or a CUE sheet.
+    Write,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::TagEdit => "tag-edit",
+            Action::Rename => "rename",
+            Action::Delete => "delete",
+            Action::Write => "write",
+        }
+    }
+}
+
+/// One line of the journal.
+#[derive(Serialize, Deserialize)]
+pub struct Entry {
+    /// Unix time.
+    pub time: u64,
+    pub command: String,
+    /// Run of the command, missing in entries written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<String>,
+    pub action: Action,
+    pub path: PathBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub before: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<String>,
+    /// Quick hash of a deleted file where it was kept, to check it is still the same file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+impl Entry {
+    pub fn new(action: Action, path: &Path) -> Self {
+        let (command, run) = COMMAND
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+            .unzip();
+        Entry {
+            time: unix_now(),
+            command: command.unwrap_or_default(),
+            run,
+            action,
+            path: std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()),
+            field: None,
+            before: None,
+            after: None,
+            hash: None,
+        }
+    }
+
+    /// Whether the entry concerns `path` or a file below it, before or after a rename.
+    pub fn concerns(&self, path: &Path) -> bool {
+        self.path.starts_with(path)
+            || (self.action == Action::Rename
+                && self
+                    .after
+                    .as_deref()
+                    .is_some_and(|after| Path::new(after).starts_with(path)))
+    }
+}
+
+/// Append entries to the journal. Failing to do so is only a warning, the change itself is done.
+pub fn record(entries: impl IntoIterator<Item = Entry>) {
+    let Some(path) = journal_path() else {
+        return;
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), fs::create_dir_all)
+        .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+        .and_then(|mut file| {
+            let mut lines = String::new();
+            for entry in entries {
+                lines.push_str(&serde_json::to_string(&entry).map_err(std::io::Error::other)?);
+                lines.push('\n');
+            }
+            file.write_all(lines.as_bytes())
+        });
+    if let Err(e) = result {
+        warn!("Could not write to the journal {}: {}", path.display(), e);
+    }
+}
+
+/// Record the fields that differ between two snapshots of a tag.
+pub fn record_tag_edit(
+    path: &Path,
+    before: &BTreeMap<String, String>,
+    after: &BTreeMap<String, String>,
+) {
+    let fields: Vec<&String> = before
+        .keys()
+        .chain(after.keys().filter(|key| !before.contains_key(*key)))
+        .filter(|key| before.get(*key) != after.get(*key))
+        .collect();
+    record(fields.into_iter().map(|field| Entry {
+        field: Some(field.clone()),
+        before: before.get(field).cloned(),
+        after: after.get(field).cloned(),
+        ..Entry::new(Action::TagEdit, path)
+    }));
+}
+
+pub fn record_rename(from: &Path, to: &Path) {
+    let to = std::path::absolute(to).unwrap_or_else(|_| to.to_path_buf());
+    record([Entry {
+        after: Some(to.display().to_string()),
+        ..Entry::new(Action::Rename, from)
+    }]);
+}
+
+pub fn record_delete(path: &Path, content: Option<String>, kept: Option<&Path>) {
+    record([Entry {
+        before: content,
+        after: kept.map(|kept| {
+            let kept = std::path::absolute(kept).unwrap_or_else(|_| kept.to_path_buf());
+            kept.display().to_string()
+        }),
+        hash: kept.and_then(|kept| quick_hash(kept).ok()).map(format_hash),
+        ..Entry::new(Action::Delete, path)
+    }]);
+}
+
+/// How hashes are written in the journal.
+pub fn format_hash(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+pub fn record_write(path: &Path, before: Option<String>, after: Option<String>) {
+    record([Entry {
+        before,
+        after,
+        ..Entry::new(Action::Write, path)
+    }]);
+}
+
+/// Every entry of the journal, oldest first. Unreadable lines are skipped.
+pub fn read() -> std::io::Result<Vec<Entry>> {
+    match journal_path() {
+        Some(path) => read_from(&path),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Every entry of the journal at `path`, e.g. a copy of it.
+pub fn read_from(path: &Path) -> std::io::Result<Vec<Entry>> {
+    let file = match fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut entries = Vec::new();
+    for line in BufReader::new(file).lines() {
+        match serde_json::from_str(&line?) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => warn!("Skipping invalid journal line: {}", e),
+        }
+    }
+    Ok(entries)
+}
+
+/// `$XDG_STATE_HOME/muman/journal.jsonl`, or `~/.local/state/muman/journal.jsonl`.
+pub fn journal_path() -> Option<PathBuf> {
+    crate::fs::state_path("journal.jsonl")
+}
@@ -1,18 +1,236 @@
-use crate::fs::Cache;
+use std::sync::atomic::{AtomicBool, Ordering};
 
-const ALLOWED_EXTENSIONS: &[&str] = &["flac"];
+use log::error;
+
+use crate::{
+    cli::{Cli, Command},
+    config::{CommandOptions, Config},
+    hooks::Event,
+    summary::Summary,
+};
+
+/// Formats scanned when neither `--extensions`, the config nor the profile picks them.
+const ALLOWED_EXTENSIONS: &[&str] = &["flac", "mp3", "ogg", "opus", "m4a", "wav", "aiff"];
+/// Audio formats recognized outside of the library scan, whatever the profile's extensions.
+const AUDIO_EXTENSIONS: &[&str] = &[
+    "flac", "mp3", "ogg", "opus", "m4a", "aac", "wav", "aiff", "aif", "wv", "ape", "dsf",
+];
+
+/// Process exit codes. These are part of the interface, scripts can branch on them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// Everything went fine.
+    Ok = 0,
+    /// The command could not run: missing library, invalid config, I/O error...
+    Failure = 1,
+    /// The command ran but some files could not be processed.
+    PartialFailure = 2,
+    /// The command ran but found nothing to work on.
+    NothingMatched = 3,
+    /// Invalid command line arguments.
+    Usage = 64,
+}
+
+/// Set by `cancel`, cleared when a command starts.
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+
+/// Ask the running command to stop, e.g. from another thread of a front end driving `run`. The
+/// walk, tag reading, hashing and MusicBrainz lookups stop at the next file or request, and the
+/// command fails without writing the cache or any other state.
+pub fn cancel() {
+    CANCELLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether `cancel` was called since the command started.
+fn cancelled() -> bool {
+    CANCELLED.load(Ordering::Relaxed)
+}
 
 mod album;
 mod artist;
+pub mod cli;
+mod commands;
+mod config;
+mod filter;
 mod fs;
+mod hooks;
+mod http;
+mod image;
+mod journal;
 mod library;
+mod lyrics;
+mod musicbrainz;
+mod output;
+mod playlist;
+mod report;
+mod summary;
+mod tag;
+mod tagdiff;
 mod track;
 
-pub fn run() {
-    let library =
-        library::DirtyLibrary::new(std::path::PathBuf::from("./tests/songs/"), Cache::new());
-    for track in &library.tracks {
-        println!("{:?}", track);
+/// Internals used by the benchmarks in `benches/`, not a stable API.
+#[doc(hidden)]
+pub mod internals {
+    pub use crate::{
+        album::base_title,
+        artist::main_artist,
+        fs::{quick_hash, recurse_directory},
+        lyrics::normalize as normalize_lyrics,
+        track::DirtyTrack,
+    };
+}
+
+/// Whether colors are turned off, by `--no-color` or `NO_COLOR`.
+pub fn no_color(cli: &Cli) -> bool {
+    cli.no_color || output::no_color_env().is_some()
+}
+
+pub fn run(cli: Cli) -> Status {
+    CANCELLED.store(false, Ordering::Relaxed);
+    output::set_quiet(cli.quiet);
+    output::set_color(no_color(&cli));
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            error!("{}", e);
+            return Status::Failure;
+        }
+    };
+    let Some(mut profile) = config.profile(cli.profile.as_deref()) else {
+        error!(
+            "Unknown profile: {}",
+            cli.profile.or(config.default_profile).unwrap_or_default()
+        );
+        return Status::Failure;
+    };
+
+    let cli_options = CommandOptions {
+        jobs: cli.jobs,
+        io_jobs: cli.io_jobs,
+        offline: cli.offline.then_some(true),
+        extensions: cli.extensions,
+        trash: cli.trash.then_some(true),
+        quarantine: cli.quarantine,
+    };
+    let settings = config.settings(
+        Some(cli.command.name()),
+        &cli_options,
+        &profile,
+        cli.profile.is_some(),
+    );
+    profile.library = settings.library.value.clone();
+    profile.extensions = settings.extensions.value.clone();
+    let offline = settings.offline.value == Some(true);
+    fs::set_io_jobs(settings.io_jobs.value.unwrap_or(fs::DEFAULT_IO_JOBS));
+    fs::set_deletion(
+        settings
+            .deletion
+            .value
+            .clone()
+            .unwrap_or(fs::Deletion::Remove),
+    );
+    // A pool of its own rather than the global one, whose size can only be set once per process
+    let pool = match rayon::ThreadPoolBuilder::new()
+        .num_threads(settings.jobs.value.unwrap_or(0))
+        .build()
+    {
+        Ok(pool) => pool,
+        Err(e) => {
+            error!("Could not start the worker threads: {}", e);
+            return Status::Failure;
+        }
+    };
+
+    if !cli.force_root
+        && let Some(cli_path) = cli.command.modified_library()
+        && let Some(path) = profile.library_path(cli_path.cloned())
+        && let Err(reason) = commands::check_library_root(&path, &profile.extensions())
+    {
+        error!(
+            "Refusing to modify {}: {}. Pass --force-root if this is really your library",
+            path.display(),
+            reason
+        );
+        return Status::Failure;
+    }
+
+    let hooks = match config.hooks(offline) {
+        Ok(hooks) => hooks,
+        Err(e) => {
+            error!("{}", e);
+            return Status::Failure;
+        }
+    };
+    let mut summary = Summary::new(cli.command.name());
+    journal::set_command(cli.command.name());
+    let status = pool.install(|| match cli.command {
+        Command::Scan {
+            library_path,
+            follow_moves,
+        } => commands::scan::run(library_path, follow_moves, &profile, &hooks, &mut summary),
+        Command::Info { files } => commands::info::run(files, &mut summary),
+        Command::Cache { action } => commands::cache::run(action, &mut summary),
+        Command::Playlist { action } => {
+            commands::playlist::run(action, &config, &profile, &hooks, &mut summary)
+        }
+        Command::Tag { action } => {
+            commands::tag::run(action, &config, &profile, &hooks, offline, &mut summary)
+        }
+        Command::Albums { action } => {
+            commands::albums::run(action, &profile, &hooks, cli.force_root, &mut summary)
+        }
+        Command::Artists { action } => commands::artists::run(action, &profile, &mut summary),
+        Command::Lyrics { action } => {
+            commands::lyrics::run(action, &config, &profile, &hooks, &mut summary)
+        }
+        Command::History {
+            path,
+            days,
+            command,
+            json,
+        } => commands::history::run(path, days, command, json, &mut summary),
+        Command::Undo {
+            journal,
+            run,
+            apply,
+            yes,
+        } => commands::undo::run(journal, run, apply || yes, yes, &mut summary),
+        Command::Bench { library_path } => {
+            commands::bench::run(library_path, &profile, &mut summary)
+        }
+        Command::Report { action } => {
+            commands::report::run(action, &config, &profile, &hooks, &mut summary)
+        }
+        Command::Backup { action } => commands::backup::run(action, &profile, &mut summary),
+        Command::Init => commands::init::run(),
+        Command::Config { action } => commands::config::run(
+            action,
+            &config,
+            cli.profile.as_deref(),
+            &profile,
+            &cli_options,
+            &settings,
+        ),
+    });
+
+    summary.finish();
+    hooks.fire(Event::CommandFinished {
+        summary: &summary,
+        status,
+    });
+    if summary.errors > 0 {
+        hooks.fire(Event::ProblemsFound {
+            summary: &summary,
+            status,
+        });
     }
-    println!("Total tracks found: {}", library.tracks.len());
+
+    summary.print();
+    if let Some(path) = cli.summary_json
+        && let Err(e) = summary.write_json(&path)
+    {
+        error!("Could not write summary to {}: {}", path.display(), e);
+    }
+
+    status
 }
@@ -1,36 +1,395 @@
-use std::path::PathBuf;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::debug;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    ALLOWED_EXTENSIONS,
-    fs::{Cache, recurse_directory},
-    track::DirtyTrack,
+    cancelled,
+    fs::{Cache, FileEntry, recurse_directory, stat, unix_now, with_io_slot, write_file},
+    summary::Summary,
+    track::{DirtyTrack, Unsupported},
 };
 
+const INDEX_PATH: &str = "index.jsonl";
+/// Bumped when `DirtyTrack` changes, so that an index written by an older version is read again
+/// from the files rather than trusted.
+const INDEX_VERSION: u32 = 1;
+
 pub struct DirtyLibrary {
-    path: PathBuf,
+    pub path: PathBuf,
     pub tracks: Vec<DirtyTrack>,
+    /// Files not in the cache before this scan.
+    pub added: Vec<PathBuf>,
+    /// Files whose cache entry was outdated.
+    pub changed: Vec<PathBuf>,
+    /// Files found at a new path, as (previous path, new path): a known file disappeared and a
+    /// file with the same size and quick hash showed up.
+    pub moved: Vec<(PathBuf, PathBuf)>,
+    /// Files that cannot be read at all, found by this scan or skipped since a previous one.
+    pub unsupported: Vec<(PathBuf, Unsupported)>,
 }
 
 impl DirtyLibrary {
-    pub fn new(path: PathBuf, cache: Cache) -> Self {
-        let tracks = recurse_directory(
-            &path,
-            true,
-            Some(&|p: &PathBuf| {
-                p.extension()
-                    .and_then(|ext| ext.to_str())
-                    .map_or(false, |ext_str| {
-                        ALLOWED_EXTENSIONS
-                            .iter()
-                            .any(|allowed_ext| allowed_ext.eq_ignore_ascii_case(ext_str))
-                    })
-            }),
-            cache.scan_count,
-        )
+    /// Scan the library and refresh the per-file entries of the cache with what was found. Tracks
+    /// come in path order, so that reports read the same from one run to the next.
+    pub fn new(
+        path: PathBuf,
+        extensions: &[String],
+        cache: &mut Cache,
+        index: &mut Index,
+        summary: &mut Summary,
+    ) -> Self {
+        let files = summary.phase("walk", |_| {
+            let mut files = recurse_directory(
+                &path,
+                true,
+                Some(&|p: &PathBuf| has_extension(p, extensions)),
+                cache.scan_count,
+            );
+            files.sort();
+            files
+        });
+        let count = files.len();
+        let library = Self::from_files(path, files, extensions, cache, index, summary);
+        cache.scan_count = Some(count);
+        library
+    }
+
+    /// Build the library from an explicit list of files instead of walking `path`, e.g. a list
+    /// read from stdin. Files with other extensions are ignored, and so are files the cache
+    /// knows cannot be read. Tags of the files unchanged since they were indexed are taken from
+    /// `index`, the others are read and indexed.
+    pub fn from_files(
+        path: PathBuf,
+        files: Vec<PathBuf>,
+        extensions: &[String],
+        cache: &mut Cache,
+        index: &mut Index,
+        summary: &mut Summary,
+    ) -> Self {
+        let mut unsupported = Vec::new();
+        let tracks: Vec<DirtyTrack> = summary.phase("tags", |summary| {
+            let files: Vec<PathBuf> = files
+                .into_iter()
+                .filter(|file_path| file_path.is_file() && has_extension(file_path, extensions))
+                .filter(|file_path| match cache.unsupported(file_path) {
+                    Some(reason) => {
+                        debug!("Skipping {}: {}", file_path.display(), reason);
+                        unsupported.push((file_path.clone(), reason));
+                        false
+                    }
+                    None => true,
+                })
+                .collect();
+            let tracks = read_indexed(files, index);
+            summary.scanned += tracks.len();
+            summary.errors += tracks.iter().filter(|t| t.read_error.is_some()).count();
+            tracks
+        });
+
+        let mut added = Vec::new();
+        let mut changed = Vec::new();
+        let mut moved = Vec::new();
+        let mut vanished = None;
+        summary.phase("cache", |_| {
+            for track in &tracks {
+                let Some(file_path) = &track.file_path else {
+                    continue;
+                };
+                if let Some(reason) = track.unsupported {
+                    match FileEntry::new(file_path, 0) {
+                        Ok(entry) => {
+                            cache.files.remove(file_path);
+                            cache.unsupported.insert(file_path.clone(), (entry, reason));
+                        }
+                        Err(e) => debug!("Could not stat {}: {}", file_path.display(), e),
+                    }
+                    unsupported.push((file_path.clone(), reason));
+                    continue;
+                }
+                cache.unsupported.remove(file_path);
+                if !cache.has_changed(file_path) {
+                    continue;
+                }
+                debug!("{} changed since the last scan", file_path.display());
+                let mut entry = FileEntry::new(file_path, track.tag_hash());
+                if cache.files.contains_key(file_path) {
+                    changed.push(file_path.clone());
+                } else if let Ok(entry) = &mut entry
+                    && let Some(previous) = vanished
+                        .get_or_insert_with(|| vanished_files(cache))
+                        .remove(&(entry.size, entry.quick_hash))
+                        .flatten()
+                {
+                    debug!("{} moved to {}", previous.display(), file_path.display());
+                    if let Some(old) = cache.files.remove(&previous) {
+                        entry.first_seen = old.first_seen;
+                    }
+                    moved.push((previous, file_path.clone()));
+                } else {
+                    added.push(file_path.clone());
+                }
+                match entry {
+                    Ok(entry) => cache.record(file_path.clone(), entry),
+                    Err(e) => debug!("Could not stat {}: {}", file_path.display(), e),
+                }
+            }
+        });
+
+        unsupported.sort_by(|a, b| a.0.cmp(&b.0));
+        DirtyLibrary {
+            path,
+            tracks,
+            added,
+            changed,
+            moved,
+            unsupported,
+        }
+    }
+}
+
+/// Cached files that no longer exist, by size and quick hash. `None` when several share them, the
+/// file that moved cannot be told apart from its duplicates.
+fn vanished_files(cache: &Cache) -> HashMap<(u64, u64), Option<PathBuf>> {
+    let mut vanished = HashMap::new();
+    for (path, entry) in &cache.files {
+        if !path.exists() {
+            vanished
+                .entry((entry.size, entry.quick_hash))
+                .and_modify(|known| *known = None)
+                .or_insert_with(|| Some(path.clone()));
+        }
+    }
+    vanished
+}
+
+/// Read the tags of the files in parallel, on the `--jobs` threads but with no more than
+/// `--io-jobs` files open at once. The order of the files is kept. Once cancelled, the files left
+/// are not read.
+pub fn read_tracks(files: Vec<PathBuf>) -> Vec<DirtyTrack> {
+    files
+        .into_par_iter()
+        .filter(|_| !cancelled())
+        .map(|file_path| with_io_slot(|| DirtyTrack::from(file_path)))
+        .collect()
+}
+
+/// Like `read_tracks`, but take the tracks of unchanged files from the index and index the
+/// others.
+fn read_indexed(files: Vec<PathBuf>, index: &mut Index) -> Vec<DirtyTrack> {
+    let read: Vec<(DirtyTrack, Option<(u64, u64)>)> = files
+        .into_par_iter()
+        .filter(|_| !cancelled())
+        .map(|file_path| {
+            with_io_slot(|| {
+                // Stat before reading, a file written meanwhile is read again next time
+                let stat = stat(&file_path).ok();
+                match stat.and_then(|stat| index.get(&file_path, stat)) {
+                    Some(track) => (track, None),
+                    None => (DirtyTrack::from(file_path), stat),
+                }
+            })
+        })
+        .collect();
+
+    let mut reused = 0;
+    let tracks: Vec<DirtyTrack> = read
         .into_iter()
-        .map(|file_path| file_path.into())
+        .map(|(track, stat)| {
+            match stat {
+                Some(stat) => index.insert(&track, stat),
+                None => reused += 1,
+            }
+            track
+        })
         .collect();
+    debug!("{} of {} tracks taken from the index", reused, tracks.len());
+    tracks
+}
+
+/// Tags read by previous scans, so that files whose size and mtime did not change are not read
+/// again. Kept next to the cache as JSON lines: a header with the version, then one file per line.
+#[derive(Default)]
+pub struct Index {
+    entries: HashMap<PathBuf, IndexEntry>,
+    /// Whether entries were added or dropped since the index was read.
+    changed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexEntry {
+    path: PathBuf,
+    size: u64,
+    mtime: u64,
+    track: DirtyTrack,
+}
+
+#[derive(Serialize, Deserialize)]
+struct IndexHeader {
+    version: u32,
+}
+
+impl Index {
+    /// Read the index, empty if it is missing or was written by another version. Damaged lines
+    /// are skipped.
+    pub fn load() -> Self {
+        let content = match fs::read_to_string(INDEX_PATH) {
+            Ok(content) => content,
+            Err(e) => {
+                debug!("No index read from {}: {}", INDEX_PATH, e);
+                return Index::default();
+            }
+        };
+        let mut lines = content.lines();
+        let version = lines
+            .next()
+            .and_then(|line| serde_json::from_str::<IndexHeader>(line).ok())
+            .map(|header| header.version);
+        if version != Some(INDEX_VERSION) {
+            debug!("Ignoring index of version {:?}", version);
+            return Index::default();
+        }
+
+        let mut entries = HashMap::new();
+        for line in lines {
+            match serde_json::from_str::<IndexEntry>(line) {
+                Ok(entry) => {
+                    entries.insert(entry.path.clone(), entry);
+                }
+                Err(e) => debug!("Invalid index line: {}", e),
+            }
+        }
+        Index {
+            entries,
+            changed: false,
+        }
+    }
+
+    /// Indexed track of the file, if its size and mtime are still those of `stat`.
+    pub fn get(&self, path: &Path, stat: (u64, u64)) -> Option<DirtyTrack> {
+        let entry = self.entries.get(path)?;
+        if (entry.size, entry.mtime) != stat {
+            return None;
+        }
+        Some(DirtyTrack {
+            file_path: Some(path.to_path_buf()),
+            ..entry.track.clone()
+        })
+    }
+
+    /// Index a track read from its file, `stat` being the size and mtime before it was read.
+    /// Tracks that could not be read are left out, and so are files modified in the last seconds:
+    /// a write in the same second would not change the mtime.
+    pub fn insert(&mut self, track: &DirtyTrack, (size, mtime): (u64, u64)) {
+        let Some(path) = &track.file_path else {
+            return;
+        };
+        if track.read_error.is_some() || mtime + 2 > unix_now() {
+            self.changed |= self.entries.remove(path).is_some();
+            return;
+        }
+        let track = DirtyTrack {
+            file_path: None,
+            ..track.clone()
+        };
+        self.entries.insert(
+            path.clone(),
+            IndexEntry {
+                path: path.clone(),
+                size,
+                mtime,
+                track,
+            },
+        );
+        self.changed = true;
+    }
+
+    /// Number of files indexed.
+    pub fn file_count(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Drop the entries of files that were removed or changed since they were indexed. Returns
+    /// how many entries were dropped.
+    pub fn vacuum(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries
+            .retain(|path, entry| stat(path).is_ok_and(|stat| stat == (entry.size, entry.mtime)));
+        let dropped = before - self.entries.len();
+        self.changed |= dropped > 0;
+        dropped
+    }
+
+    /// Size of the index file on disk, `None` if it does not exist.
+    pub fn file_size() -> Option<u64> {
+        fs::metadata(INDEX_PATH).ok().map(|m| m.len())
+    }
+
+    pub fn clear() -> std::io::Result<()> {
+        match fs::remove_file(INDEX_PATH) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write the index back if it changed, through a temporary file like the cache.
+    pub fn write_to_file(&self) -> std::io::Result<()> {
+        if !self.changed {
+            return Ok(());
+        }
+        let mut content = serde_json::to_string(&IndexHeader {
+            version: INDEX_VERSION,
+        })?;
+        content.push('\n');
+        let mut paths: Vec<&PathBuf> = self.entries.keys().collect();
+        paths.sort();
+        for path in paths {
+            // Paths that are not valid UTF-8 cannot be written as JSON, they are read every time
+            if let Ok(line) = serde_json::to_string(&self.entries[path]) {
+                content.push_str(&line);
+                content.push('\n');
+            }
+        }
+
+        let temporary = format!("{}.{}.tmp", INDEX_PATH, std::process::id());
+        let result = write_file(Path::new(&temporary), |out| {
+            out.write_all(content.as_bytes())
+        })
+        .and_then(|_| fs::rename(&temporary, INDEX_PATH));
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        result
+    }
+}
+
+pub fn has_extension(path: &Path, extensions: &[String]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext_str| {
+            extensions
+                .iter()
+                .any(|allowed_ext| allowed_ext.eq_ignore_ascii_case(ext_str))
+        })
+}
 
-        DirtyLibrary { path, tracks }
+/// Group tracks by folder and album tag, tracks without an album tag are left out. Two albums
+/// with the same name in different folders stay apart.
+pub fn group_by_album(tracks: &[DirtyTrack]) -> BTreeMap<(&Path, &str), Vec<&DirtyTrack>> {
+    let mut albums: BTreeMap<(&Path, &str), Vec<&DirtyTrack>> = BTreeMap::new();
+    for track in tracks {
+        if let (Some(folder), Some(album)) = (
+            track.file_path.as_deref().and_then(Path::parent),
+            track.album.as_deref(),
+        ) {
+            albums.entry((folder, album)).or_default().push(track);
+        }
     }
+    albums
 }
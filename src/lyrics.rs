@@ -0,0 +1,162 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use crate::AUDIO_EXTENSIONS;
+
+/// How sidecar lyrics files are named, as used in the `[lyrics]` section of the config.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SidecarNaming {
+    /// "Song.lrc", the name most players look for.
+    #[default]
+    Replace,
+    /// "Song.flac.lrc", which does not collide when "Song.flac" and "Song.mp3" sit side by side.
+    Append,
+}
+
+/// Existing sidecar lyrics files of a track: "Song.lrc", and "Song.flac.lrc" as written by some
+/// players. With the append naming "Song.lrc" is left out when another "Song.*" track could own
+/// it.
+pub fn sidecars(path: &Path, naming: SidecarNaming) -> Vec<PathBuf> {
+    let replaced = path.with_extension("lrc");
+    let appended = appended_sidecar(path);
+    let shared = naming == SidecarNaming::Append && has_namesakes(path);
+    [(!shared).then_some(replaced), Some(appended)]
+        .into_iter()
+        .flatten()
+        .filter(|sidecar| sidecar.is_file())
+        .collect()
+}
+
+/// The sidecar name that is kept when reconciling: "Song.lrc", or "Song.flac.lrc" with the
+/// append naming.
+pub fn canonical_sidecar(path: &Path, naming: SidecarNaming) -> PathBuf {
+    match naming {
+        SidecarNaming::Replace => path.with_extension("lrc"),
+        SidecarNaming::Append => appended_sidecar(path),
+    }
+}
+
+/// Sidecar files of a track that moved from `from` to `to`, with the names they take next to the
+/// new path: lyrics as "Song.lrc" or "Song.flac.lrc", and a "Song.cue" sheet. Those named after
+/// the stem stay put while another "Song.*" track still sits next to them, and none replaces an
+/// existing file.
+pub fn moved_sidecars(from: &Path, to: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let mut sidecars = vec![(appended_sidecar(from), appended_sidecar(to))];
+    if !has_namesakes(from) {
+        sidecars.push((from.with_extension("lrc"), to.with_extension("lrc")));
+        sidecars.push((from.with_extension("cue"), to.with_extension("cue")));
+    }
+    sidecars.retain(|(old, new)| old.is_file() && !new.exists());
+    sidecars
+}
+
+fn appended_sidecar(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lrc");
+    PathBuf::from(name)
+}
+
+/// Whether other audio files share the name of the track, such as "Song.mp3" next to
+/// "Song.flac".
+fn has_namesakes(path: &Path) -> bool {
+    let Some(stem) = path.file_stem() else {
+        return false;
+    };
+    let folder = path
+        .parent()
+        .filter(|folder| !folder.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let Ok(entries) = fs::read_dir(folder) else {
+        return false;
+    };
+    entries.flatten().map(|entry| entry.path()).any(|other| {
+        other.file_name() != path.file_name()
+            && other.file_stem() == Some(stem)
+            && other
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| AUDIO_EXTENSIONS.contains(&e.to_lowercase().as_str()))
+    })
+}
+
+/// Lyrics with line endings and trailing whitespace made uniform, for comparisons.
+pub fn normalize(lyrics: &str) -> String {
+    lyrics
+        .lines()
+        .map(str::trim_end)
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_string()
+}
+
+/// Whether the lyrics are synced LRC, with "[mm:ss.xx]" timestamps.
+pub fn is_synced(lyrics: &str) -> bool {
+    lyrics.lines().any(|line| {
+        line.strip_prefix('[')
+            .and_then(|rest| rest.split_once(']'))
+            .and_then(|(time, _)| time.split_once(':'))
+            .is_some_and(|(minutes, seconds)| {
+                minutes.parse::<u32>().is_ok() && seconds.parse::<f32>().is_ok()
+            })
+    })
+}
+
+/// Lines removed from `old` ('-') and added in `new` ('+'), in order. Common lines are left out.
+pub fn diff<'a>(old: &'a str, new: &'a str) -> Vec<(char, &'a str)> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    // Longest common subsequence lengths of the suffixes.
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut changes = Vec::new();
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            i += 1;
+            j += 1;
+        } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            changes.push(('-', old[i]));
+            i += 1;
+        } else {
+            changes.push(('+', new[j]));
+            j += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_trims_line_ends_and_blank_edges() {
+        assert_eq!(normalize("\n  One  \r\nTwo\t\n\n"), "One\nTwo");
+    }
+
+    #[test]
+    fn diff_lists_removed_and_added_lines_in_order() {
+        assert_eq!(
+            diff("a\nb\nc\nd", "a\nc\nx\nd"),
+            vec![('-', "b"), ('+', "x")]
+        );
+        assert_eq!(diff("a", "a"), vec![]);
+        assert_eq!(diff("", "a\nb"), vec![('+', "a"), ('+', "b")]);
+    }
+}
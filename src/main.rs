@@ -1,6 +1,37 @@
-mod cli;
-use muman::run;
+use std::process::ExitCode;
 
-fn main() {
-    run();
+use clap::Parser;
+use env_logger::WriteStyle;
+use muman::{Status, cli::Cli, no_color, run};
+
+fn main() -> ExitCode {
+    let cli = match Cli::try_parse() {
+        Ok(cli) => cli,
+        Err(e) => {
+            // Keep clap's own exit code 2 free for partial failures
+            let _ = e.print();
+            return ExitCode::from(if e.use_stderr() {
+                Status::Usage as u8
+            } else {
+                Status::Ok as u8
+            });
+        }
+    };
+
+    env_logger::Builder::new()
+        .filter_level(match cli.verbose {
+            0 if cli.quiet => log::LevelFilter::Error,
+            0 => log::LevelFilter::Warn,
+            1 => log::LevelFilter::Info,
+            2 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        })
+        .write_style(if no_color(&cli) {
+            WriteStyle::Never
+        } else {
+            WriteStyle::Auto
+        })
+        .init();
+
+    ExitCode::from(run(cli) as u8)
 }
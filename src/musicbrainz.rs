@@ -0,0 +1,57 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use ureq::Agent;
+
+use crate::cancelled;
+
+const API_URL: &str = "https://musicbrainz.org/ws/2";
+/// MusicBrainz allows one request per second on average.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Client of the MusicBrainz web service, spacing out its requests to stay under the rate limit.
+pub struct MusicBrainz {
+    agent: Agent,
+    last_request: Option<Instant>,
+}
+
+#[derive(Deserialize)]
+struct Recording {
+    #[serde(default)]
+    isrcs: Vec<String>,
+}
+
+impl MusicBrainz {
+    /// Client sending its requests with `agent`, see `http::agent`.
+    pub fn new(agent: Agent) -> Self {
+        MusicBrainz {
+            agent,
+            last_request: None,
+        }
+    }
+
+    /// ISRCs of a recording, given its MusicBrainz ID.
+    pub fn recording_isrcs(&mut self, id: &str) -> Result<Vec<String>, String> {
+        let body = self.get(&format!("{}/recording/{}?inc=isrcs&fmt=json", API_URL, id))?;
+        let recording: Recording = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+        Ok(recording.isrcs)
+    }
+
+    fn get(&mut self, url: &str) -> Result<String, String> {
+        if cancelled() {
+            return Err("cancelled".to_string());
+        }
+        if let Some(elapsed) = self.last_request.map(|last| last.elapsed()) {
+            thread::sleep(REQUEST_INTERVAL.saturating_sub(elapsed));
+        }
+        self.last_request = Some(Instant::now());
+        self.agent
+            .get(url)
+            .call()
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(|e| e.to_string())
+    }
+}
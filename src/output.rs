@@ -0,0 +1,193 @@
+use std::{
+    fmt::Display,
+    io::{IsTerminal, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+/// Whether stdout and stderr get colors, decided once at startup.
+static COLOR: AtomicBool = AtomicBool::new(false);
+static COLOR_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Terminal styles, kept to what reads well on both dark and light backgrounds.
+#[derive(Clone, Copy)]
+pub enum Style {
+    Bold,
+    Dim,
+    Error,
+    Warning,
+    Success,
+}
+
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Color the output that goes to a terminal, unless turned off.
+pub fn set_color(no_color: bool) {
+    COLOR.store(
+        !no_color && std::io::stdout().is_terminal(),
+        Ordering::Relaxed,
+    );
+    COLOR_STDERR.store(
+        !no_color && std::io::stderr().is_terminal(),
+        Ordering::Relaxed,
+    );
+}
+
+/// `NO_COLOR` when set to a non-empty value, see https://no-color.org.
+pub fn no_color_env() -> Option<std::ffi::OsString> {
+    std::env::var_os("NO_COLOR").filter(|value| !value.is_empty())
+}
+
+/// `value` in `style` if stdout gets colors, as is otherwise.
+pub fn paint(value: impl Display, style: Style) -> String {
+    styled(value, style, COLOR.load(Ordering::Relaxed))
+}
+
+/// Like `paint`, for text written to stderr.
+pub fn paint_stderr(value: impl Display, style: Style) -> String {
+    styled(value, style, COLOR_STDERR.load(Ordering::Relaxed))
+}
+
+fn styled(value: impl Display, style: Style, color: bool) -> String {
+    if !color {
+        return value.to_string();
+    }
+    let code = match style {
+        Style::Bold => "1",
+        Style::Dim => "2",
+        Style::Error => "31",
+        Style::Warning => "33",
+        Style::Success => "32",
+    };
+    format!("\x1b[{}m{}\x1b[0m", code, value)
+}
+
+/// `println!` that stays silent with `--quiet`. Errors go through `log` and are not affected.
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Like `say!` but on stderr, for progress and summaries that must not mix with data written to
+/// stdout.
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if !$crate::output::is_quiet() {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use progress;
+pub(crate) use say;
+
+/// Ask a yes/no question on stdin, anything but "y" or "yes" is a no.
+pub fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", paint(question, Style::Bold));
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Ask to pick one of `count` numbered options on stdin. An empty answer picks `default`,
+/// anything that is not a valid number picks nothing.
+pub fn choose(question: &str, count: usize, default: Option<usize>) -> Option<usize> {
+    match default {
+        Some(default) => print!(
+            "{} [1-{}, default {}] ",
+            paint(question, Style::Bold),
+            count,
+            default + 1
+        ),
+        None => print!(
+            "{} [1-{}, empty to skip] ",
+            paint(question, Style::Bold),
+            count
+        ),
+    }
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return None;
+    }
+    match answer.trim() {
+        "" => default,
+        answer => answer
+            .parse::<usize>()
+            .ok()
+            .filter(|n| (1..=count).contains(n))
+            .map(|n| n - 1),
+    }
+}
+
+/// Ask for a line of text on stdin. An empty answer, or no answer at all, gives `default`.
+pub fn ask(question: &str, default: &str) -> String {
+    if default.is_empty() {
+        print!("{} ", paint(question, Style::Bold));
+    } else {
+        print!("{} [{}] ", paint(question, Style::Bold), default);
+    }
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return default.to_string();
+    }
+    match answer.trim() {
+        "" => default.to_string(),
+        answer => answer.to_string(),
+    }
+}
+
+/// Print rows with their columns aligned, with `say!`. Cells may be painted.
+pub fn print_table(rows: &[Vec<String>]) {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate() {
+            let width = visible_width(cell);
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+    for row in rows {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            line.push_str(cell);
+            if i + 1 < row.len() {
+                line.push_str(&" ".repeat(widths[i] - visible_width(cell) + 2));
+            }
+        }
+        say!("{}", line);
+    }
+}
+
+/// Number of characters shown, without the escape sequences of `paint`.
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut in_escape = false;
+    for c in text.chars() {
+        match (in_escape, c) {
+            (false, '\x1b') => in_escape = true,
+            (false, _) => width += 1,
+            (true, 'm') => in_escape = false,
+            (true, _) => {}
+        }
+    }
+    width
+}
@@ -0,0 +1,388 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use crate::{config::ExportProfile, track::DirtyTrack};
+
+/// CUE sheet times are in minutes, seconds and frames of 1/75 s.
+const CUE_FRAMES_PER_SECOND: u64 = 75;
+/// Track numbers of a CUE sheet only have two digits.
+const CUE_MAX_TRACKS: usize = 99;
+
+/// Where a playlist is written and how its paths and lines are formatted.
+pub struct PlaylistStyle {
+    pub export: ExportProfile,
+    /// Folder of the playlist, for relative paths.
+    pub folder: Option<PathBuf>,
+    /// Library folder, replaced by the export root.
+    pub library: Option<PathBuf>,
+}
+
+impl PlaylistStyle {
+    fn path(&self, path: &Path) -> String {
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.into());
+        let in_library = self.library.as_deref().and_then(|library| {
+            absolute(path)
+                .strip_prefix(absolute(library))
+                .ok()
+                .map(PathBuf::from)
+        });
+        let path = match (&self.export.root, in_library, &self.folder) {
+            (Some(root), Some(rest), _) => {
+                format!("{}/{}", root.trim_end_matches(['/', '\\']), rest.display())
+            }
+            (_, _, Some(folder)) if self.export.relative => {
+                relative_path(&absolute(path), &absolute(folder))
+                    .display()
+                    .to_string()
+            }
+            _ => path.display().to_string(),
+        };
+        if self.export.backslashes {
+            path.replace('/', "\\")
+        } else {
+            path
+        }
+    }
+
+    /// Write the playlist text, which is built with "\n" line endings.
+    fn write(&self, mut out: impl Write, text: &[u8], bom: bool) -> io::Result<()> {
+        if bom && self.export.bom {
+            out.write_all("\u{feff}".as_bytes())?;
+        }
+        for line in text.split_inclusive(|b| *b == b'\n') {
+            match line.strip_suffix(b"\n") {
+                Some(line) if self.export.crlf => {
+                    out.write_all(line)?;
+                    out.write_all(b"\r\n")?;
+                }
+                _ => out.write_all(line)?,
+            }
+        }
+        out.flush()
+    }
+}
+
+/// Absolute `path` relative to the absolute `base`, going up with ".." as needed.
+fn relative_path(path: &Path, base: &Path) -> PathBuf {
+    let path: Vec<Component> = path.components().collect();
+    let base: Vec<Component> = base.components().collect();
+    let common = path.iter().zip(&base).take_while(|(a, b)| a == b).count();
+    if common == 0
+        && path
+            .first()
+            .is_some_and(|c| matches!(c, Component::Prefix(_)))
+    {
+        return path.iter().collect();
+    }
+    base[common..]
+        .iter()
+        .map(|_| Component::ParentDir)
+        .chain(path[common..].iter().copied())
+        .collect()
+}
+
+/// Absolute `path` with "." and ".." folded, without looking at the filesystem: the path may no
+/// longer exist.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let path = if path.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        path
+    };
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.into());
+    let mut normalized = PathBuf::new();
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Point the entries of an M3U playlist or a CUE sheet at the new paths of moved files, `moved`
+/// mapping normalized previous paths to normalized new ones. Relative entries are resolved from
+/// `read_from`, the folder the playlist was in, and rewritten relative to `write_from`, the one it
+/// is in now. Entries keep their style: absolute or relative, slashes or backslashes. `None` when
+/// no entry changed.
+pub fn retarget(
+    text: &str,
+    cue: bool,
+    read_from: &Path,
+    write_from: &Path,
+    moved: &HashMap<PathBuf, PathBuf>,
+) -> Option<String> {
+    let (read_from, write_from) = (normalize_path(read_from), normalize_path(write_from));
+    let mut changed = false;
+    let mut result = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        let content = line.trim_end_matches(['\r', '\n']);
+        let ending = &line[content.len()..];
+        let (bom, content) = match content.strip_prefix('\u{feff}') {
+            Some(rest) => ("\u{feff}", rest),
+            None => ("", content),
+        };
+        let entry = if cue {
+            cue_file_entry(content)
+        } else {
+            m3u_entry(content)
+        };
+        let new_entry = entry.and_then(|(start, end)| {
+            retarget_entry(&content[start..end], &read_from, &write_from, moved)
+                .map(|new| (start, end, new))
+        });
+        result.push_str(bom);
+        match new_entry {
+            Some((start, end, new)) => {
+                changed = true;
+                result.push_str(&content[..start]);
+                result.push_str(&new);
+                result.push_str(&content[end..]);
+            }
+            None => result.push_str(content),
+        }
+        result.push_str(ending);
+    }
+    changed.then_some(result)
+}
+
+/// New path of a playlist entry if it points at a moved file.
+fn retarget_entry(
+    entry: &str,
+    read_from: &Path,
+    write_from: &Path,
+    moved: &HashMap<PathBuf, PathBuf>,
+) -> Option<String> {
+    if entry.contains("://") {
+        return None;
+    }
+    let backslashes = entry.contains('\\');
+    let path = PathBuf::from(entry.replace('\\', "/"));
+    let new = moved.get(&normalize_path(&read_from.join(&path)))?;
+    let new = if path.is_absolute() {
+        new.display().to_string()
+    } else {
+        relative_path(new, write_from).display().to_string()
+    };
+    Some(if backslashes {
+        new.replace('/', "\\")
+    } else {
+        new
+    })
+}
+
+/// Byte range of the path of an M3U line, every line but comments and blank ones.
+fn m3u_entry(line: &str) -> Option<(usize, usize)> {
+    let trimmed = line.trim();
+    (!trimmed.is_empty() && !trimmed.starts_with('#')).then_some((0, line.len()))
+}
+
+/// Byte range of the file name of a `FILE "name" TYPE` line of a CUE sheet.
+fn cue_file_entry(line: &str) -> Option<(usize, usize)> {
+    let rest = line.trim_start().strip_prefix("FILE ")?;
+    let start = line.len() - rest.len();
+    match rest.strip_prefix('"') {
+        Some(quoted) => Some((start + 1, start + 1 + quoted.find('"')?)),
+        None => Some((start, start + rest.rfind(' ')?)),
+    }
+}
+
+/// Write an extended M3U playlist of the tracks, in order.
+pub fn write_m3u(out: impl Write, tracks: &[&DirtyTrack], style: &PlaylistStyle) -> io::Result<()> {
+    let mut text = Vec::new();
+    writeln!(text, "#EXTM3U")?;
+    for track in tracks {
+        let Some(path) = &track.file_path else {
+            continue;
+        };
+        writeln!(
+            text,
+            "#EXTINF:{},{} - {}\n{}",
+            track.duration.map_or(-1, i64::from),
+            track.artist.as_deref().unwrap_or_default(),
+            track.title.as_deref().unwrap_or_default(),
+            style.path(path)
+        )?;
+    }
+    style.write(out, &text, true)
+}
+
+/// Write a CUE sheet of the tracks, in order. Each track references its own file, unless
+/// `single_file` is given: then all tracks are indexed in that file, each starting where the
+/// durations of the previous ones add up to.
+pub fn write_cue(
+    out: impl Write,
+    title: Option<&str>,
+    tracks: &[&DirtyTrack],
+    single_file: Option<&Path>,
+    style: &PlaylistStyle,
+) -> io::Result<()> {
+    let tracks: Vec<(&DirtyTrack, &Path)> = tracks
+        .iter()
+        .filter_map(|track| Some((*track, track.file_path.as_deref()?)))
+        .collect();
+    if tracks.len() > CUE_MAX_TRACKS {
+        return Err(io::Error::other(format!(
+            "a CUE sheet holds at most {} tracks, got {}",
+            CUE_MAX_TRACKS,
+            tracks.len()
+        )));
+    }
+    let mut text = Vec::new();
+    let performer = |track: &DirtyTrack| track.album_artist.clone().or(track.artist.clone());
+    let first_performer = tracks.first().and_then(|(track, _)| performer(track));
+    if first_performer.is_some() && tracks.iter().all(|(t, _)| performer(t) == first_performer) {
+        writeln!(
+            text,
+            "PERFORMER {}",
+            cue_quoted(first_performer.as_deref().unwrap_or_default())
+        )?;
+    }
+    if let Some(title) = title {
+        writeln!(text, "TITLE {}", cue_quoted(title))?;
+    }
+    if let Some(file) = single_file {
+        writeln!(
+            text,
+            "FILE {} {}",
+            cue_quoted(&style.path(file)),
+            cue_file_type(file)
+        )?;
+    }
+
+    let mut offset_ms = 0;
+    for (number, (track, path)) in tracks.into_iter().enumerate() {
+        if single_file.is_none() {
+            writeln!(
+                text,
+                "FILE {} {}",
+                cue_quoted(&style.path(path)),
+                cue_file_type(path)
+            )?;
+        }
+        writeln!(text, "  TRACK {:02} AUDIO", number + 1)?;
+        if let Some(title) = &track.title {
+            writeln!(text, "    TITLE {}", cue_quoted(title))?;
+        }
+        if let Some(artist) = &track.artist {
+            writeln!(text, "    PERFORMER {}", cue_quoted(artist))?;
+        }
+        if let Some(isrc) = track.isrc.as_deref().filter(|isrc| isrc.len() == 12) {
+            writeln!(text, "    ISRC {}", isrc)?;
+        }
+        writeln!(text, "    INDEX 01 {}", cue_time(offset_ms))?;
+        if single_file.is_some() {
+            offset_ms += track.duration_ms.unwrap_or(0);
+        }
+    }
+    style.write(out, &text, false)
+}
+
+/// Time as "MM:SS:FF", rounded down to the frame.
+fn cue_time(ms: u64) -> String {
+    let frames = ms * CUE_FRAMES_PER_SECOND / 1000;
+    format!(
+        "{:02}:{:02}:{:02}",
+        frames / (CUE_FRAMES_PER_SECOND * 60),
+        frames / CUE_FRAMES_PER_SECOND % 60,
+        frames % CUE_FRAMES_PER_SECOND
+    )
+}
+
+/// CUE sheets have no escaping, so double quotes are replaced.
+fn cue_quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "'"))
+}
+
+fn cue_file_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("mp3") => "MP3",
+        Some("aif" | "aiff") => "AIFF",
+        _ => "WAVE",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_path_goes_up_to_the_common_folder() {
+        let relative = |path: &str, base: &str| relative_path(Path::new(path), Path::new(base));
+        assert_eq!(
+            relative("/music/A/01.flac", "/music/A"),
+            Path::new("01.flac")
+        );
+        assert_eq!(
+            relative("/music/B/01.flac", "/music/A"),
+            Path::new("../B/01.flac")
+        );
+        assert_eq!(
+            relative("/music/B/01.flac", "/music/lists/car"),
+            Path::new("../../B/01.flac")
+        );
+        assert_eq!(relative("/music/A", "/music/A"), Path::new(""));
+    }
+
+    fn moved() -> HashMap<PathBuf, PathBuf> {
+        HashMap::from([(
+            PathBuf::from("/music/A/01.flac"),
+            PathBuf::from("/music/B/01.flac"),
+        )])
+    }
+
+    #[test]
+    fn retarget_keeps_the_style_of_m3u_entries() {
+        let lists = Path::new("/music/lists");
+        let text =
+            "#EXTM3U\r\n../A/01.flac\r\n/music/A/01.flac\r\n..\\A\\01.flac\r\n../A/02.flac\r\n";
+        assert_eq!(
+            retarget(text, false, lists, lists, &moved()).as_deref(),
+            Some(
+                "#EXTM3U\r\n../B/01.flac\r\n/music/B/01.flac\r\n..\\B\\01.flac\r\n../A/02.flac\r\n"
+            )
+        );
+    }
+
+    #[test]
+    fn retarget_leaves_urls_and_unmoved_entries() {
+        let lists = Path::new("/music/lists");
+        let text = "#EXTINF:1,A\nhttp://radio/A/01.flac\n../A/02.flac\n";
+        assert_eq!(retarget(text, false, lists, lists, &moved()), None);
+    }
+
+    #[test]
+    fn retarget_rewrites_cue_files_from_where_the_sheet_is_now() {
+        let text = "TITLE \"A\"\nFILE \"01.flac\" WAVE\n  TRACK 01 AUDIO\n";
+        let retargeted = retarget(
+            text,
+            true,
+            Path::new("/music/A"),
+            Path::new("/music/lists"),
+            &moved(),
+        );
+        assert_eq!(
+            retargeted.as_deref(),
+            Some("TITLE \"A\"\nFILE \"../B/01.flac\" WAVE\n  TRACK 01 AUDIO\n")
+        );
+    }
+
+    #[test]
+    fn cue_time_rounds_down_to_the_frame() {
+        assert_eq!(cue_time(0), "00:00:00");
+        assert_eq!(cue_time(999), "00:00:74");
+        assert_eq!(cue_time(61_500), "01:01:37");
+        assert_eq!(cue_time(3_600_000), "60:00:00");
+    }
+}
@@ -0,0 +1,183 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    hash::Hasher,
+    io::{self, Write},
+    path::PathBuf,
+};
+
+use crate::{
+    fs::Fnv64,
+    image::{ImageInfo, SMALL_COVER},
+    track::{DirtyTrack, Unsupported},
+};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;background:#fafafa;color:#222}\
+h2{border-bottom:1px solid #ccc;padding-bottom:.2em}\
+.grid{display:grid;grid-template-columns:repeat(auto-fill,minmax(180px,1fr));gap:1.5em}\
+figure{margin:0}\
+.cover{width:100%;aspect-ratio:1;object-fit:cover;background:#ddd;display:flex;\
+align-items:center;justify-content:center;color:#888}\
+figcaption{margin-top:.4em;font-size:.9em}\
+.badge{display:inline-block;font-size:.75em;padding:.1em .4em;margin:.2em .2em 0 0;\
+border-radius:.3em;background:#ccc}\
+.hi-res{background:#c9a227}.lossless{background:#7cb342}.lossy{background:#90a4ae}\
+.warning{background:#e57373}";
+
+/// Audio quality of an album, from its worst track.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quality {
+    /// Lossy format, with its bitrate in kbps.
+    Lossy(Option<u32>),
+    /// Lossless at CD quality or below, with bit depth and sample rate.
+    Lossless(u8, u32),
+    /// Lossless above CD quality.
+    HiRes(u8, u32),
+}
+
+impl Quality {
+    pub fn of(track: &DirtyTrack) -> Self {
+        match (track.bit_depth, track.sample_rate) {
+            (Some(bits), Some(rate)) if bits > 16 || rate > 48000 => Quality::HiRes(bits, rate),
+            (Some(bits), Some(rate)) => Quality::Lossless(bits, rate),
+            _ => Quality::Lossy(track.bitrate),
+        }
+    }
+
+    fn class(&self) -> &'static str {
+        match self {
+            Quality::Lossy(_) => "lossy",
+            Quality::Lossless(..) => "lossless",
+            Quality::HiRes(..) => "hi-res",
+        }
+    }
+}
+
+impl fmt::Display for Quality {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Quality::Lossy(Some(bitrate)) => write!(f, "{} kbps", bitrate),
+            Quality::Lossy(None) => write!(f, "Lossy"),
+            Quality::Lossless(bits, rate) | Quality::HiRes(bits, rate) => {
+                write!(f, "{}/{}", bits, *rate as f32 / 1000.0)
+            }
+        }
+    }
+}
+
+/// Short ID of a finding, hashed from its kind and what it is about rather than from its place in
+/// the report, so that the same problem keeps its ID from one run to the next.
+pub fn finding_id(kind: &str, subject: &str) -> String {
+    let mut hasher = Fnv64::default();
+    hasher.write(kind.as_bytes());
+    hasher.write(&[0]);
+    hasher.write(subject.as_bytes());
+    format!("{:012x}", hasher.finish() >> 16)
+}
+
+/// One album of the HTML report.
+pub struct AlbumCard {
+    /// Stable ID of the album, see `finding_id`.
+    pub id: String,
+    pub title: String,
+    pub year: Option<u32>,
+    /// Release country, or the country shared by the ISRCs.
+    pub country: Option<String>,
+    pub tracks: usize,
+    /// Cover image, relative to the report.
+    pub cover: Option<String>,
+    /// Format and size of the cover, when its header could be read.
+    pub cover_info: Option<ImageInfo>,
+    pub quality: Quality,
+    pub missing_lyrics: usize,
+}
+
+/// Write a static HTML page with a grid of album cards per artist, then the files that could not
+/// be read. Warnings carry their finding ID in a `data-finding` attribute.
+pub fn write_html(
+    mut out: impl Write,
+    title: &str,
+    artists: &BTreeMap<String, Vec<AlbumCard>>,
+    unsupported: &[(PathBuf, Unsupported)],
+) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>{}</title>", escape(title))?;
+    writeln!(out, "<style>{}</style></head><body>", STYLE)?;
+    writeln!(out, "<h1>{}</h1>", escape(title))?;
+
+    for (artist, albums) in artists {
+        writeln!(out, "<h2>{}</h2>\n<div class=\"grid\">", escape(artist))?;
+        for album in albums {
+            writeln!(out, "<figure id=\"{}\">", album.id)?;
+            match &album.cover {
+                Some(cover) => writeln!(
+                    out,
+                    "<img class=\"cover\" loading=\"lazy\" src=\"{}\" alt=\"\">",
+                    escape(cover)
+                )?,
+                None => writeln!(out, "<div class=\"cover\">No cover</div>")?,
+            }
+            write!(out, "<figcaption><b>{}</b>", escape(&album.title))?;
+            match (album.year, &album.country) {
+                (Some(year), Some(country)) => write!(out, " ({}, {})", year, escape(country))?,
+                (Some(year), None) => write!(out, " ({})", year)?,
+                (None, Some(country)) => write!(out, " ({})", escape(country))?,
+                (None, None) => {}
+            }
+            write!(out, "<br>{} tracks<br>", album.tracks)?;
+            write!(
+                out,
+                "<span class=\"badge {}\">{}</span>",
+                album.quality.class(),
+                album.quality
+            )?;
+            match album.cover_info {
+                Some(info) if info.is_smaller_than(SMALL_COVER) => {
+                    let text = format!("Small art: {}", info);
+                    warning(&mut out, "small-art", &album.id, &text)?
+                }
+                Some(info) => write!(out, "<span class=\"badge\">{}</span>", info)?,
+                None if album.cover.is_none() => warning(&mut out, "no-art", &album.id, "No art")?,
+                None => {}
+            }
+            if album.missing_lyrics > 0 {
+                let text = format!("No lyrics: {}/{}", album.missing_lyrics, album.tracks);
+                warning(&mut out, "missing-lyrics", &album.id, &text)?;
+            }
+            writeln!(out, "</figcaption></figure>")?;
+        }
+        writeln!(out, "</div>")?;
+    }
+
+    if !unsupported.is_empty() {
+        writeln!(out, "<h2>Unsupported files</h2>\n<ul>")?;
+        for (path, reason) in unsupported {
+            let path = path.display().to_string();
+            write!(out, "<li>{} ", escape(&path))?;
+            warning(&mut out, "unsupported", &path, &reason.to_string())?;
+            writeln!(out, "</li>")?;
+        }
+        writeln!(out, "</ul>")?;
+    }
+
+    writeln!(out, "</body></html>")?;
+    out.flush()
+}
+
+/// Warning badge of the finding `kind` about `subject`.
+fn warning(out: &mut impl Write, kind: &str, subject: &str, text: &str) -> io::Result<()> {
+    write!(
+        out,
+        "<span class=\"badge warning\" data-finding=\"{}\">{}</span>",
+        finding_id(kind, subject),
+        escape(text)
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
@@ -0,0 +1,99 @@
+use std::{path::Path, time::Instant};
+
+use serde::Serialize;
+
+use crate::{
+    fs::write_file,
+    output::{Style, paint_stderr, progress},
+};
+
+/// Counters and timings of a command run, printed at the end and optionally dumped as JSON.
+#[derive(Default, Serialize)]
+pub struct Summary {
+    pub command: String,
+    pub scanned: usize,
+    pub matched: usize,
+    pub modified: usize,
+    pub skipped: usize,
+    pub errors: usize,
+    pub bytes_freed: u64,
+    pub phases: Vec<Phase>,
+    /// Total wall time in seconds.
+    pub elapsed: f64,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+#[derive(Serialize)]
+pub struct Phase {
+    pub name: String,
+    /// Wall time in seconds.
+    pub elapsed: f64,
+}
+
+impl Summary {
+    pub fn new(command: &str) -> Self {
+        Summary {
+            command: command.to_string(),
+            started: Some(Instant::now()),
+            ..Default::default()
+        }
+    }
+
+    /// Run `f` and record how long it took under `name`.
+    pub fn phase<T>(&mut self, name: &str, f: impl FnOnce(&mut Self) -> T) -> T {
+        let start = Instant::now();
+        let result = f(self);
+        self.phases.push(Phase {
+            name: name.to_string(),
+            elapsed: start.elapsed().as_secs_f64(),
+        });
+        result
+    }
+
+    pub fn finish(&mut self) {
+        if let Some(started) = self.started {
+            self.elapsed = started.elapsed().as_secs_f64();
+        }
+    }
+
+    /// Print a short report, commands that did not record any phase have nothing to report.
+    pub fn print(&self) {
+        if self.phases.is_empty() {
+            return;
+        }
+
+        let errors = if self.errors > 0 {
+            paint_stderr(format!("errors {}", self.errors), Style::Error)
+        } else {
+            format!("errors {}", self.errors)
+        };
+        progress!(
+            "Scanned {}, matched {}, modified {}, skipped {}, {}",
+            self.scanned,
+            self.matched,
+            self.modified,
+            self.skipped,
+            errors
+        );
+        if self.bytes_freed > 0 {
+            progress!("Freed {} bytes", self.bytes_freed);
+        }
+        let phases: Vec<String> = self
+            .phases
+            .iter()
+            .map(|p| format!("{} {:.2}s", p.name, p.elapsed))
+            .collect();
+        progress!(
+            "{}",
+            paint_stderr(
+                format!("Done in {:.2}s ({})", self.elapsed, phases.join(", ")),
+                Style::Dim
+            )
+        );
+    }
+
+    pub fn write_json(&self, path: &Path) -> std::io::Result<()> {
+        write_file(path, |out| Ok(serde_json::to_writer_pretty(out, self)?))
+    }
+}
@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, fs::File, hash::Hasher, path::Path};
+
+use lofty::{
+    config::{ParseOptions, WriteOptions},
+    file::{AudioFile, TaggedFileExt},
+    flac::FlacFile,
+    ogg::{OpusFile, VorbisFile},
+    picture::{MimeType, PictureType},
+    tag::{ItemKey, Tag, TagType},
+};
+
+use crate::{fs::Fnv64, journal};
+
+/// Apply `edit` to the primary tag of a file, creating the tag if the file has none. The file is
+/// only written back if `edit` reports a change, the returned value tells whether it was. The
+/// fields that changed are recorded in the journal.
+pub fn edit_tag(path: &Path, edit: impl FnOnce(&mut Tag) -> bool) -> lofty::error::Result<bool> {
+    let mut tagged_file = lofty::read_from_path(path)?;
+    if tagged_file.primary_tag().is_none() {
+        tagged_file.insert_tag(Tag::new(tagged_file.primary_tag_type()));
+    }
+    let before = tagged_file.primary_tag().map(snapshot).unwrap_or_default();
+    let changed = tagged_file.primary_tag_mut().is_some_and(edit);
+    if changed {
+        tagged_file.save_to_path(path, WriteOptions::default())?;
+        let after = tagged_file.primary_tag().map(snapshot).unwrap_or_default();
+        journal::record_tag_edit(path, &before, &after);
+    }
+    Ok(changed)
+}
+
+/// Fields of the primary tag of a file before and after applying `edit` in memory, without
+/// writing anything. Used to preview changes.
+pub fn preview_tag_edit(
+    path: &Path,
+    edit: impl FnOnce(&mut Tag) -> bool,
+) -> lofty::error::Result<(Fields, Fields)> {
+    let tagged_file = lofty::read_from_path(path)?;
+    let mut tag = tagged_file
+        .primary_tag()
+        .cloned()
+        .unwrap_or_else(|| Tag::new(tagged_file.primary_tag_type()));
+    let before = fields(&tag);
+    edit(&mut tag);
+    Ok((before, fields(&tag)))
+}
+
+/// Values of a tag by the field name stored in the file, one per item.
+pub type Fields = BTreeMap<String, Vec<String>>;
+
+/// Every value of a tag by field name, for diffs that are read back. Items this tag type has no
+/// name for are left out as they are not saved anyway.
+fn fields(tag: &Tag) -> Fields {
+    let mut fields = Fields::new();
+    for item in tag.items() {
+        let Some(name) = stored_name(item.key(), tag.tag_type()) else {
+            continue;
+        };
+        let value = item.value().text().unwrap_or("<binary>");
+        fields.entry(name).or_default().push(value.to_string());
+    }
+    if tag.picture_count() > 0 {
+        let bytes: usize = tag.pictures().iter().map(|p| p.data().len()).sum();
+        fields.insert(
+            "<pictures>".to_string(),
+            vec![format!("{} ({} bytes)", tag.picture_count(), bytes)],
+        );
+    }
+    fields
+}
+
+/// Values of a tag by field name, several values of a field joined with "; ".
+fn snapshot(tag: &Tag) -> BTreeMap<String, String> {
+    let mut fields: BTreeMap<String, String> = BTreeMap::new();
+    for item in tag.items() {
+        let value = item.value().text().unwrap_or("<binary>");
+        fields
+            .entry(item_name(item.key(), tag.tag_type()))
+            .and_modify(|values| {
+                values.push_str("; ");
+                values.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    if tag.picture_count() > 0 {
+        let bytes: usize = tag.pictures().iter().map(|p| p.data().len()).sum();
+        fields.insert(
+            "<pictures>".to_string(),
+            format!("{} ({} bytes)", tag.picture_count(), bytes),
+        );
+    }
+    fields
+}
+
+/// Name of an item as stored in the file, e.g. "COMMENT" in Vorbis comments.
+pub fn item_name(key: &ItemKey, tag_type: TagType) -> String {
+    stored_name(key, tag_type).unwrap_or_else(|| format!("{:?}", key))
+}
+
+/// Name of an item as stored in the file, `None` for keys this tag type cannot store.
+fn stored_name(key: &ItemKey, tag_type: TagType) -> Option<String> {
+    match key {
+        ItemKey::Unknown(name) => Some(name.clone()),
+        _ => key.map_key(tag_type, false).map(str::to_string),
+    }
+}
+
+/// Whether a user given key like "ripping-tool" or "comment" designates the item. Case and
+/// punctuation are ignored, and both the stored name and lofty's name are accepted.
+pub fn key_matches(pattern: &str, key: &ItemKey, tag_type: TagType) -> bool {
+    let normalize = |s: &str| -> String {
+        s.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let pattern = normalize(pattern);
+    pattern == normalize(&item_name(key, tag_type)) || pattern == normalize(&format!("{:?}", key))
+}
+
+/// Vendor string of the Vorbis comments of FLAC and Ogg files. lofty exposes it as an
+/// `EncoderSoftware` item although it is mandatory and cannot be removed.
+pub fn vorbis_vendor(path: &Path) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let options = ParseOptions::new().read_properties(false);
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let vendor = match extension.as_str() {
+        "flac" => FlacFile::read_from(&mut file, options)
+            .ok()?
+            .vorbis_comments()?
+            .vendor()
+            .to_string(),
+        "ogg" => VorbisFile::read_from(&mut file, options)
+            .ok()?
+            .vorbis_comments()
+            .vendor()
+            .to_string(),
+        "opus" => OpusFile::read_from(&mut file, options)
+            .ok()?
+            .vorbis_comments()
+            .vendor()
+            .to_string(),
+        _ => return None,
+    };
+    Some(vendor)
+}
+
+/// Front cover embedded in the primary tag, or else its first picture, with a file extension
+/// matching its format.
+pub fn embedded_cover(path: &Path) -> Option<(Vec<u8>, &'static str)> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let tag = tagged_file.primary_tag()?;
+    let picture = tag
+        .get_picture_type(PictureType::CoverFront)
+        .or_else(|| tag.pictures().first())?;
+    let extension = match picture.mime_type() {
+        Some(MimeType::Png) => "png",
+        Some(MimeType::Gif) => "gif",
+        Some(MimeType::Bmp) => "bmp",
+        _ => "jpg",
+    };
+    Some((picture.data().to_vec(), extension))
+}
+
+/// Digest and total size of all pictures embedded in the primary tag, `None` without pictures.
+pub fn pictures_digest(path: &Path) -> Option<(u64, u64)> {
+    let tagged_file = lofty::read_from_path(path).ok()?;
+    let pictures = tagged_file.primary_tag()?.pictures();
+    if pictures.is_empty() {
+        return None;
+    }
+    let mut hasher = Fnv64::default();
+    let mut size = 0;
+    for picture in pictures {
+        hasher.write(picture.data());
+        size += picture.data().len() as u64;
+    }
+    Some((hasher.finish(), size))
+}
+
+/// Whether an MP4 file is DRM protected, like FairPlay ".m4p" files: its sample descriptions hold
+/// a protection scheme ("sinf") box. The whole file is read, so only call this on files that
+/// failed to open.
+pub fn is_protected(path: &Path) -> bool {
+    let is_mp4 = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| ["m4a", "m4p", "m4b", "mp4"].contains(&e.to_lowercase().as_str()));
+    is_mp4 && std::fs::read(path).is_ok_and(|data| data.windows(4).any(|w| w == b"sinf"))
+}
@@ -0,0 +1,141 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use lofty::tag::{ItemKey, ItemValue, Tag, TagItem};
+
+use crate::tag::Fields;
+
+/// Planned tag changes of one file: values before and after by field name, empty when the field
+/// is missing.
+pub struct TagDiff {
+    pub path: PathBuf,
+    pub fields: BTreeMap<String, (Vec<String>, Vec<String>)>,
+}
+
+impl TagDiff {
+    /// Fields that differ between two snapshots of a tag, `None` if none does.
+    pub fn between(path: &Path, before: &Fields, after: &Fields) -> Option<Self> {
+        let values = |fields: &Fields, field: &str| fields.get(field).cloned().unwrap_or_default();
+        let fields: BTreeMap<String, (Vec<String>, Vec<String>)> = before
+            .keys()
+            .chain(after.keys())
+            .filter(|field| before.get(*field) != after.get(*field))
+            .map(|field| (field.clone(), (values(before, field), values(after, field))))
+            .collect();
+        (!fields.is_empty()).then(|| TagDiff {
+            path: path.to_path_buf(),
+            fields,
+        })
+    }
+
+    /// Whether the tag still holds the values the diff was made from.
+    pub fn applies_to(&self, current: &Fields) -> bool {
+        self.fields.iter().all(|(field, (before, _))| {
+            current.get(field).map_or(&[][..], Vec::as_slice) == before.as_slice()
+        })
+    }
+
+    /// Set every field of the diff to its new value, returning whether any changed. Pseudo-fields
+    /// such as "<pictures>" and binary values cannot be restored from text and are left alone.
+    pub fn apply(&self, tag: &mut Tag) -> bool {
+        let mut changed = false;
+        for (field, (_, after)) in &self.fields {
+            if field.starts_with('<') || after.iter().any(|value| value == "<binary>") {
+                continue;
+            }
+            let key = ItemKey::from_key(tag.tag_type(), field);
+            if tag.get_strings(&key).eq(after.iter().map(String::as_str)) {
+                continue;
+            }
+            changed = true;
+            tag.remove_key(&key);
+            for value in after {
+                tag.push(TagItem::new(
+                    key.clone(),
+                    ItemValue::Text(value.to_string()),
+                ));
+            }
+        }
+        changed
+    }
+}
+
+/// Write diffs in unified diff style, a "---"/"+++" header per file then "-FIELD=value" and
+/// "+FIELD=value" lines, one per value. Backslashes and line breaks in values are escaped.
+pub fn format(diffs: &[TagDiff]) -> String {
+    let mut text = String::new();
+    for diff in diffs {
+        text.push_str(&format!("--- {}\n", diff.path.display()));
+        text.push_str(&format!("+++ {}\n", diff.path.display()));
+        for (field, (before, after)) in &diff.fields {
+            for value in before {
+                text.push_str(&format!("-{}={}\n", field, escape(value)));
+            }
+            for value in after {
+                text.push_str(&format!("+{}={}\n", field, escape(value)));
+            }
+        }
+    }
+    text
+}
+
+/// Read diffs written by `format`, possibly edited by hand. Lines starting with '#' are ignored.
+pub fn parse(text: &str) -> Result<Vec<TagDiff>, String> {
+    let mut diffs: Vec<TagDiff> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        if line.is_empty() || line.starts_with('#') || line.starts_with("+++ ") {
+            continue;
+        }
+        if let Some(path) = line.strip_prefix("--- ") {
+            diffs.push(TagDiff {
+                path: PathBuf::from(path),
+                fields: BTreeMap::new(),
+            });
+            continue;
+        }
+
+        let error = || format!("line {}: expected -FIELD=value or +FIELD=value", number + 1);
+        let (sign, change) = line.split_at_checked(1).ok_or_else(error)?;
+        let (field, value) = change.split_once('=').ok_or_else(error)?;
+        let Some(diff) = diffs.last_mut() else {
+            return Err(format!(
+                "line {}: change before any \"---\" file",
+                number + 1
+            ));
+        };
+        let values = diff.fields.entry(field.to_string()).or_default();
+        match sign {
+            "-" => values.0.push(unescape(value)),
+            "+" => values.1.push(unescape(value)),
+            _ => return Err(error()),
+        }
+    }
+    Ok(diffs)
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
@@ -1,85 +1,360 @@
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
 
 use lofty::{
+    error::ErrorKind,
     file::{AudioFile, TaggedFileExt},
-    tag::ItemValue,
+    tag::ItemKey,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    album::Album,
+    artist::Artist,
+    fs::Fnv64,
+    tag::{is_protected, item_name},
 };
 
-use crate::{album::Album, artist::Artist};
+/// Keys read into the fields of `DirtyTrack`, every other one goes to `extra`. Lyrics are left out
+/// of both as they can be long.
+const FIXED_KEYS: &[ItemKey] = &[
+    ItemKey::TrackTitle,
+    ItemKey::TrackArtist,
+    ItemKey::AlbumTitle,
+    ItemKey::AlbumArtist,
+    ItemKey::FlagCompilation,
+    ItemKey::Genre,
+    ItemKey::Composer,
+    ItemKey::Label,
+    ItemKey::CatalogNumber,
+    ItemKey::Barcode,
+    ItemKey::TrackNumber,
+    ItemKey::TrackTotal,
+    ItemKey::DiscNumber,
+    ItemKey::ReleaseDate,
+    ItemKey::RecordingDate,
+    ItemKey::Year,
+    ItemKey::Isrc,
+    ItemKey::Lyrics,
+];
+
+/// Why a file cannot be read at all. Such files are remembered by the cache and left out of
+/// later scans until they change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Unsupported {
+    /// Encrypted with DRM, such as FairPlay protected ".m4p" files.
+    Protected,
+    /// Not in a format lofty can read, or damaged.
+    Unreadable,
+}
+
+impl Unsupported {
+    /// Name used in the cache file.
+    pub fn name(self) -> &'static str {
+        match self {
+            Unsupported::Protected => "protected",
+            Unsupported::Unreadable => "unreadable",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "protected" => Some(Unsupported::Protected),
+            "unreadable" => Some(Unsupported::Unreadable),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unsupported::Protected => write!(f, "DRM protected"),
+            Unsupported::Unreadable => write!(f, "unreadable"),
+        }
+    }
+}
 
-#[derive(Debug)]
+/// Tags and audio properties of a file. Kept in the library index, without the path and errors.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct DirtyTrack {
-    title: Option<String>,
-    artist: Option<String>,
-    album: Option<String>,
-    genre: Option<String>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub compilation: bool,
+    pub genre: Option<String>,
+    pub composer: Option<String>,
+
+    pub label: Option<String>,
+    pub catalog_number: Option<String>,
+    pub barcode: Option<String>,
 
-    duration: Option<u32>,
-    isrc: Option<String>,
-    bitrate: Option<u32>,
+    pub duration: Option<u32>,
+    /// Exact duration in milliseconds, for CUE sheet indexes.
+    pub duration_ms: Option<u64>,
+    pub isrc: Option<String>,
+    /// MusicBrainz recording ID, as written by Picard.
+    pub recording_id: Option<String>,
+    pub bitrate: Option<u32>,
+    pub sample_rate: Option<u32>,
+    /// Only known for lossless formats.
+    pub bit_depth: Option<u8>,
+    /// Whether the tag holds lyrics, sidecar files are not looked at.
+    pub embedded_lyrics: bool,
+    /// Whether the tag holds at least one picture.
+    pub embedded_cover: bool,
 
-    track_number: Option<u32>,
-    disc_number: Option<u32>,
-    year: Option<u32>,
+    pub track_number: Option<u32>,
+    pub track_total: Option<u32>,
+    pub disc_number: Option<u32>,
+    pub year: Option<u32>,
+    /// Release date as tagged, usually "YYYY" or "YYYY-MM-DD".
+    pub date: Option<String>,
+    /// Every other text field by its name in the file, e.g. "REPLAYGAIN_TRACK_GAIN" or "MOOD".
+    /// Several values of a field are joined with "; ".
+    pub extra: BTreeMap<String, String>,
 
+    #[serde(skip)]
     pub file_path: Option<PathBuf>,
+    /// Why the file could not be read, if it could not.
+    #[serde(skip)]
+    pub read_error: Option<String>,
+    /// Set when the file itself is at fault rather than a passing I/O error.
+    #[serde(skip)]
+    pub unsupported: Option<Unsupported>,
 }
 
 impl DirtyTrack {
+    /// Hash of the tag values only, used by the cache to notice tag edits.
+    pub fn tag_hash(&self) -> u64 {
+        let mut hasher = Fnv64::default();
+        self.title.hash(&mut hasher);
+        self.artist.hash(&mut hasher);
+        self.album.hash(&mut hasher);
+        self.album_artist.hash(&mut hasher);
+        self.compilation.hash(&mut hasher);
+        self.genre.hash(&mut hasher);
+        self.composer.hash(&mut hasher);
+        self.label.hash(&mut hasher);
+        self.catalog_number.hash(&mut hasher);
+        self.barcode.hash(&mut hasher);
+        self.isrc.hash(&mut hasher);
+        self.track_number.hash(&mut hasher);
+        self.track_total.hash(&mut hasher);
+        self.disc_number.hash(&mut hasher);
+        self.year.hash(&mut hasher);
+        self.date.hash(&mut hasher);
+        self.extra.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Value of a field of `extra`, ignoring case and punctuation so "replaygain-track-gain"
+    /// finds "REPLAYGAIN_TRACK_GAIN".
+    pub fn extra_field(&self, name: &str) -> Option<&str> {
+        let normalize = |s: &str| -> String {
+            s.chars()
+                .filter(|c| c.is_alphanumeric())
+                .flat_map(char::to_lowercase)
+                .collect()
+        };
+        let name = normalize(name);
+        self.extra
+            .iter()
+            .find(|(key, _)| normalize(key) == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Tempo in beats per minute, from the BPM tag (TBPM in ID3v2) written by beat detectors.
+    pub fn bpm(&self) -> Option<f64> {
+        ["bpm", "tbpm"]
+            .iter()
+            .find_map(|name| self.extra_field(name))
+            .and_then(|bpm| bpm.trim().parse::<f64>().ok())
+            .filter(|bpm| *bpm > 0.0)
+    }
+
+    /// ReplayGain track gain in dB, e.g. -7.5 for "-7.50 dB". The louder the track, the lower.
+    pub fn track_gain(&self) -> Option<f64> {
+        let gain = self.extra_field("replaygain_track_gain")?;
+        gain.trim()
+            .trim_end_matches("dB")
+            .trim_end_matches("db")
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    /// Country the release was issued in, from the RELEASECOUNTRY tag Picard writes, e.g. "JP" or
+    /// "XW" for worldwide.
+    pub fn release_country(&self) -> Option<String> {
+        ["releasecountry", "musicbrainz album release country"]
+            .iter()
+            .find_map(|name| self.extra_field(name))
+            .map(|country| country.trim().to_uppercase())
+            .filter(|country| !country.is_empty())
+    }
+
+    /// Country of the registrant of the recording, the first two letters of its ISRC.
+    pub fn isrc_country(&self) -> Option<String> {
+        let isrc = self.isrc.as_deref()?.trim().replace('-', "");
+        let country = isrc.get(..2)?;
+        (isrc.len() == 12 && country.chars().all(|c| c.is_ascii_alphabetic()))
+            .then(|| country.to_uppercase())
+    }
+
+    /// Human readable name and value of every known field, missing ones are left out.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let number = |number: Option<u32>, total: Option<u32>| match (number, total) {
+            (Some(n), Some(t)) => Some(format!("{}/{}", n, t)),
+            (n, _) => n.map(|n| n.to_string()),
+        };
+
+        [
+            ("Title", self.title.clone()),
+            ("Artist", self.artist.clone()),
+            ("Album", self.album.clone()),
+            ("Album artist", self.album_artist.clone()),
+            ("Compilation", self.compilation.then(|| "yes".to_string())),
+            ("Genre", self.genre.clone()),
+            ("Composer", self.composer.clone()),
+            ("Year", self.year.map(|y| y.to_string())),
+            ("Date", self.date.clone()),
+            ("Track", number(self.track_number, self.track_total)),
+            ("Disc", number(self.disc_number, None)),
+            (
+                "Duration",
+                self.duration.map(|d| format!("{}:{:02}", d / 60, d % 60)),
+            ),
+            ("Bitrate", self.bitrate.map(|b| format!("{} kbps", b))),
+            ("ISRC", self.isrc.clone()),
+            ("ISRC country", self.isrc_country()),
+            ("Country", self.release_country()),
+            ("Label", self.label.clone()),
+            ("Catalog number", self.catalog_number.clone()),
+            ("Barcode", self.barcode.clone()),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|v| (name, v)))
+        .collect()
+    }
+
+    /// Month of the release date, when the date is more precise than a year.
+    pub fn release_month(&self) -> Option<u32> {
+        self.date.as_deref()?.get(5..7)?.parse().ok()
+    }
+
     fn fill_metadata(&mut self) {
-        if let Some(path) = &self.file_path {
-            if let Ok(tagged_file) = lofty::read_from_path(path) {
-                if let Some(tag) = tagged_file.primary_tag() {
-                    self.title = tag
-                        .get_string(&lofty::tag::ItemKey::TrackTitle)
-                        .map(|s| s.to_string());
-                    self.artist = tag
-                        .get_string(&lofty::tag::ItemKey::TrackArtist)
-                        .map(|s| s.to_string());
-                    self.album = tag
-                        .get_string(&lofty::tag::ItemKey::AlbumTitle)
-                        .map(|s| s.to_string());
-                    self.genre = tag
-                        .get_string(&lofty::tag::ItemKey::Genre)
-                        .map(|s| s.to_string());
-                    self.track_number = tag
-                        .get_string(&lofty::tag::ItemKey::TrackNumber)
-                        .map_or(None, |n| n.parse::<u32>().ok());
-                    self.disc_number = tag
-                        .get_string(&lofty::tag::ItemKey::DiscNumber)
-                        .map_or(None, |n| n.parse::<u32>().ok());
-                    self.year = tag
-                        .get_string(&lofty::tag::ItemKey::Year)
-                        .map_or(None, |n| n.parse::<u32>().ok());
-                    self.isrc = tag
-                        .get_string(&lofty::tag::ItemKey::Isrc)
-                        .map(|s| s.to_string());
+        let Some(path) = &self.file_path else {
+            return;
+        };
+        let tagged_file = match lofty::read_from_path(path) {
+            Ok(tagged_file) => tagged_file,
+            Err(e) => {
+                if !matches!(e.kind(), ErrorKind::Io(_)) {
+                    self.unsupported = Some(if is_protected(path) {
+                        Unsupported::Protected
+                    } else {
+                        Unsupported::Unreadable
+                    });
                 }
+                self.read_error = Some(e.to_string());
+                return;
+            }
+        };
+
+        if let Some(tag) = tagged_file.primary_tag() {
+            self.title = tag
+                .get_string(&lofty::tag::ItemKey::TrackTitle)
+                .map(|s| s.to_string());
+            self.artist = tag
+                .get_string(&lofty::tag::ItemKey::TrackArtist)
+                .map(|s| s.to_string());
+            self.album = tag
+                .get_string(&lofty::tag::ItemKey::AlbumTitle)
+                .map(|s| s.to_string());
+            self.album_artist = tag
+                .get_string(&lofty::tag::ItemKey::AlbumArtist)
+                .map(|s| s.to_string());
+            self.compilation = tag
+                .get_string(&lofty::tag::ItemKey::FlagCompilation)
+                .is_some_and(|flag| flag == "1" || flag.eq_ignore_ascii_case("true"));
+            self.genre = tag
+                .get_string(&lofty::tag::ItemKey::Genre)
+                .map(|s| s.to_string());
+            self.composer = tag
+                .get_string(&lofty::tag::ItemKey::Composer)
+                .map(|s| s.to_string());
+            self.label = tag
+                .get_string(&lofty::tag::ItemKey::Label)
+                .map(|s| s.to_string());
+            self.catalog_number = tag
+                .get_string(&lofty::tag::ItemKey::CatalogNumber)
+                .map(|s| s.to_string());
+            self.barcode = tag
+                .get_string(&lofty::tag::ItemKey::Barcode)
+                .map(|s| s.to_string());
+            let track_number = tag.get_string(&lofty::tag::ItemKey::TrackNumber);
+            self.track_number = track_number.and_then(parse_number);
+            self.track_total = tag
+                .get_string(&lofty::tag::ItemKey::TrackTotal)
+                .and_then(parse_number)
+                .or_else(|| track_number.and_then(|n| n.split_once('/')?.1.trim().parse().ok()));
+            self.disc_number = tag
+                .get_string(&lofty::tag::ItemKey::DiscNumber)
+                .and_then(parse_number);
+            self.date = [
+                lofty::tag::ItemKey::ReleaseDate,
+                lofty::tag::ItemKey::RecordingDate,
+                lofty::tag::ItemKey::Year,
+            ]
+            .iter()
+            .find_map(|key| tag.get_string(key))
+            .map(|s| s.to_string());
+            self.year = tag
+                .get_string(&lofty::tag::ItemKey::Year)
+                .and_then(|n| n.parse::<u32>().ok())
+                .or_else(|| self.date.as_deref()?.get(..4)?.parse().ok());
+            self.isrc = tag
+                .get_string(&lofty::tag::ItemKey::Isrc)
+                .map(|s| s.to_string());
+            self.recording_id = tag
+                .get_string(&lofty::tag::ItemKey::MusicBrainzRecordingId)
+                .map(|s| s.to_string());
+            self.embedded_lyrics = tag.get_string(&lofty::tag::ItemKey::Lyrics).is_some();
+            self.embedded_cover = tag.picture_count() > 0;
 
-                let properties = tagged_file.properties();
-                self.duration = Some(properties.duration().as_secs() as u32);
-                self.bitrate = properties.audio_bitrate();
+            for item in tag.items().filter(|item| !FIXED_KEYS.contains(item.key())) {
+                let Some(value) = item.value().text() else {
+                    continue;
+                };
+                self.extra
+                    .entry(item_name(item.key(), tag.tag_type()))
+                    .and_modify(|values| {
+                        values.push_str("; ");
+                        values.push_str(value);
+                    })
+                    .or_insert_with(|| value.to_string());
             }
         }
+
+        let properties = tagged_file.properties();
+        self.duration = Some(properties.duration().as_secs() as u32);
+        self.duration_ms = Some(properties.duration().as_millis() as u64);
+        self.bitrate = properties.audio_bitrate();
+        self.sample_rate = properties.sample_rate();
+        self.bit_depth = properties.bit_depth();
     }
 }
 
-impl Default for DirtyTrack {
-    fn default() -> Self {
-        DirtyTrack {
-            title: None,
-            artist: None,
-            album: None,
-            genre: None,
-            duration: None,
-            isrc: None,
-            bitrate: None,
-            track_number: None,
-            disc_number: None,
-            year: None,
-            file_path: None,
-        }
-    }
+/// Parse values such as "3", "03" or "3/12" to their leading number.
+pub fn parse_number(value: &str) -> Option<u32> {
+    value.split('/').next()?.trim().parse().ok()
 }
 
 impl From<PathBuf> for DirtyTrack {
@@ -93,6 +368,7 @@ impl From<PathBuf> for DirtyTrack {
     }
 }
 
+#[allow(dead_code)]
 pub struct Track {
     title: String,
 